@@ -0,0 +1,227 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::grad::Node;
+
+// ============= ACTIVATION FUNCTIONS =============
+// anything that can be applied elementwise after a neuron's w.x + b.
+// implement this to plug a new activation into Neuron / Layer through
+// Activation::Custom without touching the rest of the crate
+pub trait ActivationFn: fmt::Debug + Send + Sync {
+    fn apply(&self, x: &Node) -> Node;
+
+    // used by predict; the default goes through a throwaway node, override it
+    // with the plain f64 formula when that matters
+    fn apply_f64(&self, x: f64) -> f64 {
+        self.apply(&Node::new(x)).val()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tanh;
+impl ActivationFn for Tanh {
+    fn apply(&self, x: &Node) -> Node {
+        x.tanh()
+    }
+    fn apply_f64(&self, x: f64) -> f64 {
+        x.tanh()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HardTanh;
+impl ActivationFn for HardTanh {
+    fn apply(&self, x: &Node) -> Node {
+        x.hardtanh()
+    }
+    fn apply_f64(&self, x: f64) -> f64 {
+        x.clamp(-1.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relu;
+impl ActivationFn for Relu {
+    fn apply(&self, x: &Node) -> Node {
+        x.relu()
+    }
+    fn apply_f64(&self, x: f64) -> f64 {
+        x.max(0.0)
+    }
+}
+
+// relu capped at 6
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relu6;
+impl ActivationFn for Relu6 {
+    fn apply(&self, x: &Node) -> Node {
+        x.relu6()
+    }
+    fn apply_f64(&self, x: f64) -> f64 {
+        x.clamp(0.0, 6.0)
+    }
+}
+
+// smooth, strictly positive approximation of max(x, 0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Softplus;
+impl ActivationFn for Softplus {
+    fn apply(&self, x: &Node) -> Node {
+        x.softplus()
+    }
+    fn apply_f64(&self, x: f64) -> f64 {
+        x.max(0.0) + (-x.abs()).exp().ln_1p()
+    }
+}
+
+// identity, for output layers that feed a softmax or loss directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Linear;
+impl ActivationFn for Linear {
+    fn apply(&self, x: &Node) -> Node {
+        x.clone()
+    }
+    fn apply_f64(&self, x: f64) -> f64 {
+        x
+    }
+}
+
+
+// ============= ACTIVATION =============
+// the activation a neuron applies: one of the built-ins, or any ActivationFn
+#[derive(Debug, Clone)]
+pub enum Activation {
+    Tanh,
+    HardTanh,
+    Relu,
+    Relu6,
+    Softplus,
+    Linear,
+    // Arc rather than Box so neurons stay Clone and the batch-parallel
+    // predict can share it across threads
+    Custom(Arc<dyn ActivationFn>),
+}
+
+impl Activation {
+    pub fn custom(f: impl ActivationFn + 'static) -> Activation {
+        Activation::Custom(Arc::new(f))
+    }
+
+    fn as_fn(&self) -> &dyn ActivationFn {
+        match self {
+            Activation::Tanh => &Tanh,
+            Activation::HardTanh => &HardTanh,
+            Activation::Relu => &Relu,
+            Activation::Relu6 => &Relu6,
+            Activation::Softplus => &Softplus,
+            Activation::Linear => &Linear,
+            Activation::Custom(f) => f.as_ref(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Activation::Tanh => "tanh",
+            Activation::HardTanh => "hardtanh",
+            Activation::Relu => "relu",
+            Activation::Relu6 => "relu6",
+            Activation::Softplus => "softplus",
+            Activation::Linear => "linear",
+            Activation::Custom(_) => "custom",
+        }
+    }
+
+    pub fn apply(&self, x: &Node) -> Node {
+        self.as_fn().apply(x)
+    }
+
+    // same as apply on a plain f64, for predict
+    pub fn apply_f64(&self, x: f64) -> f64 {
+        self.as_fn().apply_f64(x)
+    }
+}
+
+// custom activations compare by identity
+impl PartialEq for Activation {
+    fn eq(&self, other: &Activation) -> bool {
+        match (self, other) {
+            (Activation::Custom(a), Activation::Custom(b)) => Arc::ptr_eq(a, b),
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+// the name of an activation that couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseActivationError(pub String);
+
+impl fmt::Display for ParseActivationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown activation {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseActivationError {}
+
+// parses the built-in names returned by Activation::name, ignoring case,
+// e.g. "ReLU".parse::<Activation>(). custom activations can't be parsed
+impl FromStr for Activation {
+    type Err = ParseActivationError;
+
+    fn from_str(s: &str) -> Result<Activation, ParseActivationError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "tanh" => Ok(Activation::Tanh),
+            "hardtanh" => Ok(Activation::HardTanh),
+            "relu" => Ok(Activation::Relu),
+            "relu6" => Ok(Activation::Relu6),
+            "softplus" => Ok(Activation::Softplus),
+            "linear" => Ok(Activation::Linear),
+            _ => Err(ParseActivationError(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grad::Layer;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // leaky relu with slope 0.1 below zero, built from existing ops
+    #[derive(Debug)]
+    struct LeakyRelu;
+    impl ActivationFn for LeakyRelu {
+        fn apply(&self, x: &Node) -> Node {
+            x.clamp(0.0, f64::INFINITY) + Node::constant(0.1) * x.clamp(f64::NEG_INFINITY, 0.0)
+        }
+    }
+
+    #[test]
+    fn custom_activation_in_a_layer() {
+        let layer = Layer::with_rng(2, 3, Activation::custom(LeakyRelu), &mut StdRng::seed_from_u64(1));
+        let x = [1.5, -2.0];
+        let outputs = layer.forward(x.iter().map(|&v| Node::new(v)).collect());
+        // forward and the default apply_f64 behind predict agree
+        let predicted = layer.predict(&x);
+        for (o, p) in outputs.iter().zip(predicted.iter()) {
+            assert_eq!(o.val(), *p);
+        }
+        assert_eq!(LeakyRelu.apply_f64(-2.0), -0.2);
+        assert_eq!(Activation::custom(LeakyRelu).name(), "custom");
+    }
+
+    #[test]
+    fn parses_names_ignoring_case() {
+        assert_eq!("ReLU".parse::<Activation>(), Ok(Activation::Relu));
+        assert_eq!(" Tanh ".parse::<Activation>(), Ok(Activation::Tanh));
+        for act in [Activation::HardTanh, Activation::Relu, Activation::Relu6, Activation::Softplus, Activation::Linear] {
+            assert_eq!(act.name().parse::<Activation>(), Ok(act));
+        }
+
+        let err = "banana".parse::<Activation>().unwrap_err();
+        assert_eq!(err, ParseActivationError("banana".to_string()));
+        assert_eq!(err.to_string(), "unknown activation \"banana\"");
+        assert!("custom".parse::<Activation>().is_err());
+    }
+}
@@ -0,0 +1,416 @@
+// ============= ARENA GRAPH =============
+// an alternative to Node for building large graphs: every value lives in one
+// Vec owned by the Graph and is referred to by index, so an op costs a push
+// instead of an Rc<RefCell> allocation plus a Vec for its children.
+// a node can only refer to nodes created before it, so index order is
+// already a topological order and backward is a single reverse sweep.
+
+use crate::grad::EXP_CLAMP;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    None,
+    Add(usize, usize),
+    Sub(usize, usize),
+    Mul(usize, usize),
+    MulAdd(usize, usize, usize),
+    Tanh(usize),
+    TanhFast(usize),
+    Exp(usize),
+    Ln(usize),
+    Softplus(usize),
+    Relu(usize),
+    Abs(usize),
+    Powf(usize, f64),
+    Powi(usize, i32),
+    Pow(usize, usize),
+    Clamp(usize, f64, f64),
+    ScaleGrad(usize, f64),
+}
+
+impl Op {
+    // indices of the nodes this op reads
+    fn children(self) -> impl Iterator<Item = usize> {
+        let (a, b, c) = match self {
+            Op::None => (None, None, None),
+            Op::MulAdd(a, b, c) => (Some(a), Some(b), Some(c)),
+            Op::Add(a, b) | Op::Sub(a, b) | Op::Mul(a, b) | Op::Pow(a, b) => (Some(a), Some(b), None),
+            Op::Tanh(a) | Op::TanhFast(a) | Op::Exp(a) | Op::Ln(a) | Op::Softplus(a) | Op::Relu(a) | Op::Abs(a) | Op::Powf(a, _) | Op::Powi(a, _) | Op::Clamp(a, ..) | Op::ScaleGrad(a, _) => (Some(a), None, None),
+        };
+        a.into_iter().chain(b).chain(c)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Param {
+    val: f64,
+    grad: f64,
+    op: Op,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    params: Vec<Param>,
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph { params: Vec::new() }
+    }
+
+    // preallocates room for `capacity` nodes so building doesn't reallocate
+    pub fn with_capacity(capacity: usize) -> Graph {
+        Graph { params: Vec::with_capacity(capacity) }
+    }
+
+    fn push(&mut self, val: f64, op: Op) -> NodeId {
+        self.params.push(Param { val, grad: 0.0, op });
+        NodeId(self.params.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    pub fn value(&mut self, val: f64) -> NodeId {
+        self.push(val, Op::None)
+    }
+
+    pub fn val(&self, id: NodeId) -> f64 {
+        self.params[id.0].val
+    }
+    pub fn grad(&self, id: NodeId) -> f64 {
+        self.params[id.0].grad
+    }
+    pub fn set_grad(&mut self, id: NodeId, grad: f64) {
+        self.params[id.0].grad = grad;
+    }
+
+    pub fn add(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let val = self.val(a) + self.val(b);
+        self.push(val, Op::Add(a.0, b.0))
+    }
+
+    pub fn mul(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let val = self.val(a) * self.val(b);
+        self.push(val, Op::Mul(a.0, b.0))
+    }
+
+    pub fn sub(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let val = self.val(a) - self.val(b);
+        self.push(val, Op::Sub(a.0, b.0))
+    }
+
+    // a * b + c as a single node
+    pub fn mul_add(&mut self, a: NodeId, b: NodeId, c: NodeId) -> NodeId {
+        let val = self.val(a) * self.val(b) + self.val(c);
+        self.push(val, Op::MulAdd(a.0, b.0, c.0))
+    }
+
+    pub fn tanh(&mut self, a: NodeId) -> NodeId {
+        let val = self.val(a).tanh();
+        self.push(val, Op::Tanh(a.0))
+    }
+
+    // the same rational approximation as Node::tanh_fast
+    pub fn tanh_fast(&mut self, a: NodeId) -> NodeId {
+        let x = self.val(a);
+        let val = if x.abs() >= 3.0 {
+            x.signum()
+        } else {
+            let x2 = x * x;
+            x * (27.0 + x2) / (27.0 + 9.0 * x2)
+        };
+        self.push(val, Op::TanhFast(a.0))
+    }
+
+    pub fn square(&mut self, a: NodeId) -> NodeId {
+        self.mul(a, a)
+    }
+
+    // clamped like Node::exp
+    pub fn exp(&mut self, a: NodeId) -> NodeId {
+        let val = self.val(a).clamp(-EXP_CLAMP, EXP_CLAMP).exp();
+        self.push(val, Op::Exp(a.0))
+    }
+
+    pub fn ln(&mut self, a: NodeId) -> NodeId {
+        let val = self.val(a).ln();
+        self.push(val, Op::Ln(a.0))
+    }
+
+    // ln(1 + e^x) without overflow, as in Node::softplus
+    pub fn softplus(&mut self, a: NodeId) -> NodeId {
+        let x = self.val(a);
+        let val = x.max(0.0) + (-x.abs()).exp().ln_1p();
+        self.push(val, Op::Softplus(a.0))
+    }
+
+    pub fn relu(&mut self, a: NodeId) -> NodeId {
+        let val = self.val(a).max(0.0);
+        self.push(val, Op::Relu(a.0))
+    }
+
+    pub fn abs(&mut self, a: NodeId) -> NodeId {
+        let val = self.val(a).abs();
+        self.push(val, Op::Abs(a.0))
+    }
+
+    pub fn powf(&mut self, a: NodeId, exp: f64) -> NodeId {
+        let val = self.val(a).powf(exp);
+        self.push(val, Op::Powf(a.0, exp))
+    }
+
+    // integer power; n = 0 gives a plain 1.0 value, as Node::powi gives a constant
+    pub fn powi(&mut self, a: NodeId, n: i32) -> NodeId {
+        if n == 0 {
+            return self.value(1.0);
+        }
+        let val = self.val(a).powi(n);
+        self.push(val, Op::Powi(a.0, n))
+    }
+
+    // a^b with a differentiable exponent; a must be positive, as for Node::pow
+    pub fn pow(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        assert!(self.val(a) > 0.0, "pow needs a positive base, got {}", self.val(a));
+        let val = self.val(a).powf(self.val(b));
+        self.push(val, Op::Pow(a.0, b.0))
+    }
+
+    pub fn sqrt(&mut self, a: NodeId) -> NodeId {
+        self.powf(a, 0.5)
+    }
+
+    pub fn recip(&mut self, a: NodeId) -> NodeId {
+        self.powf(a, -1.0)
+    }
+
+    // gradient passes through inside [min, max] and is zero where the value was clipped
+    pub fn clamp(&mut self, a: NodeId, min: f64, max: f64) -> NodeId {
+        let val = self.val(a).clamp(min, max);
+        self.push(val, Op::Clamp(a.0, min, max))
+    }
+
+    pub fn hardtanh(&mut self, a: NodeId) -> NodeId {
+        self.clamp(a, -1.0, 1.0)
+    }
+
+    pub fn scale_grad(&mut self, a: NodeId, factor: f64) -> NodeId {
+        let val = self.val(a);
+        self.push(val, Op::ScaleGrad(a.0, factor))
+    }
+
+    pub fn zero_grad(&mut self) {
+        for param in self.params.iter_mut() {
+            param.grad = 0.0;
+        }
+    }
+
+    // same semantics as Node::backward_pass; seed the root first.
+    // only nodes root depends on are swept, so grads left on unrelated
+    // parts of the graph by an earlier pass aren't propagated again
+    pub fn backward(&mut self, root: NodeId) {
+        debug_assert!(
+            self.grad(root) != 0.0,
+            "backward called on a node with zero grad; seed the root with set_grad(1.0) first"
+        );
+        let mut reached = vec![false; root.0 + 1];
+        reached[root.0] = true;
+        for i in (0..=root.0).rev() {
+            if !reached[i] {
+                continue;
+            }
+            let Param { val, grad, op } = self.params[i].clone();
+            for child in op.children() {
+                reached[child] = true;
+            }
+            match op {
+                Op::Add(a, b) => {
+                    self.params[a].grad += grad;
+                    self.params[b].grad += grad;
+                }
+                Op::Sub(a, b) => {
+                    self.params[a].grad += grad;
+                    self.params[b].grad -= grad;
+                }
+                Op::Mul(a, b) => {
+                    let (val_a, val_b) = (self.params[a].val, self.params[b].val);
+                    self.params[a].grad += val_b * grad;
+                    self.params[b].grad += val_a * grad;
+                }
+                Op::MulAdd(a, b, c) => {
+                    let (val_a, val_b) = (self.params[a].val, self.params[b].val);
+                    self.params[a].grad += val_b * grad;
+                    self.params[b].grad += val_a * grad;
+                    self.params[c].grad += grad;
+                }
+                Op::Tanh(a) | Op::TanhFast(a) => self.params[a].grad += (1.0 - val * val) * grad,
+                Op::Exp(a) => {
+                    if self.params[a].val.abs() <= EXP_CLAMP {
+                        self.params[a].grad += val * grad;
+                    }
+                }
+                Op::Ln(a) => self.params[a].grad += grad / self.params[a].val,
+                Op::Softplus(a) => {
+                    // sigmoid, written so e^x is only taken for x < 0
+                    let x = self.params[a].val;
+                    let sigmoid = if x >= 0.0 {
+                        1.0 / (1.0 + (-x).exp())
+                    } else {
+                        let e = x.exp();
+                        e / (1.0 + e)
+                    };
+                    self.params[a].grad += sigmoid * grad;
+                }
+                Op::Relu(a) => {
+                    if self.params[a].val > 0.0 {
+                        self.params[a].grad += grad;
+                    }
+                }
+                Op::Abs(a) => {
+                    let x = self.params[a].val;
+                    if x != 0.0 {
+                        self.params[a].grad += x.signum() * grad;
+                    }
+                }
+                Op::Powf(a, exp) => {
+                    let der = exp * self.params[a].val.powf(exp - 1.0);
+                    self.params[a].grad += der * grad;
+                }
+                Op::Powi(a, n) => {
+                    let der = n as f64 * self.params[a].val.powi(n - 1);
+                    self.params[a].grad += der * grad;
+                }
+                Op::Pow(a, b) => {
+                    let (base, exp) = (self.params[a].val, self.params[b].val);
+                    self.params[a].grad += exp * base.powf(exp - 1.0) * grad;
+                    self.params[b].grad += val * base.ln() * grad;
+                }
+                Op::Clamp(a, min, max) => {
+                    let x = self.params[a].val;
+                    if x >= min && x <= max {
+                        self.params[a].grad += grad;
+                    }
+                }
+                Op::ScaleGrad(a, factor) => self.params[a].grad += factor * grad,
+                Op::None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grad::Node;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn backward_ignores_grads_outside_the_roots_graph() {
+        let mut g = Graph::new();
+        let x = g.value(3.0);
+        let r1 = g.square(x);
+        g.set_grad(r1, 1.0);
+        g.backward(r1);
+        assert_eq!(g.grad(x), 6.0);
+
+        let y = g.value(1.0);
+        let r2 = g.add(y, y);
+        g.set_grad(r2, 1.0);
+        g.backward(r2);
+        assert_eq!(g.grad(x), 6.0);
+        assert_eq!(g.grad(y), 2.0);
+    }
+
+    #[test]
+    fn gradients_match_node() {
+        // tanh(x * w - b)^0.5 + e^x + ln(e^x) + (e^x)^w, scaled, on both representations
+        let mut g = Graph::new();
+        let (x, w, b) = (g.value(0.7), g.value(-1.3), g.value(0.2));
+        let xw = g.mul(x, w);
+        let z = g.sub(xw, b);
+        let t = g.tanh(z);
+        let t2 = g.square(t);
+        let s = g.sqrt(t2);
+        let e = g.exp(x);
+        let l = g.ln(e);
+        let se = g.add(s, e);
+        let sel = g.add(se, l);
+        let ew = g.pow(e, w);
+        let sum = g.add(sel, ew);
+        let out = g.scale_grad(sum, 0.5);
+        g.set_grad(out, 1.0);
+        g.backward(out);
+
+        let (nx, nw, nb) = (Node::new(0.7), Node::new(-1.3), Node::new(0.2));
+        let nt = (nx.clone() * nw.clone() - nb.clone()).tanh();
+        let nout = (nt.square().sqrt() + nx.exp() + nx.exp().ln() + nx.exp().pow(&nw)).scale_grad(0.5);
+        nout.set_grad(1.0);
+        nout.backward_pass();
+
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        assert!(close(g.val(out), nout.val()));
+        assert!(close(g.grad(x), nx.grad()));
+        assert!(close(g.grad(w), nw.grad()));
+        assert!(close(g.grad(b), nb.grad()));
+    }
+
+    #[test]
+    fn ops_match_node() {
+        // inside and outside the clamp range
+        for &x in [0.3, 2.0].iter() {
+            assert_matches_node(&[x], |g, v| g.clamp(v[0], -1.0, 1.0), |n| n[0].clamp(-1.0, 1.0));
+        }
+        assert_matches_node(&[1.5, -2.0, 0.25], |g, v| g.mul_add(v[0], v[1], v[2]), |n| n[0].mul_add(&n[1], &n[2]));
+        for &n in [3, -1, 0].iter() {
+            assert_matches_node(&[1.7], |g, v| g.powi(v[0], n), |x| x[0].powi(n));
+        }
+        for &x in [-1000.0, -0.5, 0.7, 1000.0].iter() {
+            assert_matches_node(&[x], |g, v| g.softplus(v[0]), |n| n[0].softplus());
+        }
+        for &x in [-0.5, 0.0, 0.7].iter() {
+            assert_matches_node(&[x], |g, v| g.relu(v[0]), |n| n[0].relu());
+        }
+        // beyond the exp clamp the value is capped and the grad is zero
+        for &x in [-1000.0, 1.2, 1000.0].iter() {
+            assert_matches_node(&[x], |g, v| g.exp(v[0]), |n| n[0].exp());
+        }
+        for &x in [-4.0, 0.5, 2.9].iter() {
+            assert_matches_node(&[x], |g, v| g.tanh_fast(v[0]), |n| n[0].tanh_fast());
+        }
+        for &x in [-0.5, 0.0, 0.7].iter() {
+            assert_matches_node(&[x], |g, v| g.abs(v[0]), |n| n[0].abs());
+        }
+    }
+
+    // runs the same function on both representations and compares the
+    // output value and the gradient of every input
+    fn assert_matches_node(
+        inputs: &[f64],
+        on_graph: impl Fn(&mut Graph, &[NodeId]) -> NodeId,
+        on_node: impl Fn(&[Node]) -> Node,
+    ) {
+        let mut g = Graph::new();
+        let ids: Vec<NodeId> = inputs.iter().map(|&v| g.value(v)).collect();
+        let out = on_graph(&mut g, &ids);
+        g.set_grad(out, 1.0);
+        g.backward(out);
+
+        let nodes: Vec<Node> = inputs.iter().map(|&v| Node::new(v)).collect();
+        let nout = on_node(&nodes);
+        nout.set_grad(1.0);
+        nout.backward_pass();
+
+        assert_close(g.val(out), nout.val(), 1e-12);
+        for (&id, node) in ids.iter().zip(nodes.iter()) {
+            assert_close(g.grad(id), node.grad(), 1e-12);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::ops::Deref;
+use std::path::Path;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+// ============= DATASET =============
+// (inputs, targets) examples. derefs to a slice, so a &Dataset can be passed
+// anywhere the trainer takes &[(Vec<f64>, Vec<f64>)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Dataset {
+    pub examples: Vec<(Vec<f64>, Vec<f64>)>,
+}
+
+impl Dataset {
+    pub fn new(examples: Vec<(Vec<f64>, Vec<f64>)>) -> Dataset {
+        Dataset { examples }
+    }
+
+    // loads a CSV file where each row holds n_features inputs followed by
+    // n_targets targets. see from_csv_reader for the accepted format
+    pub fn from_csv<P: AsRef<Path>>(
+        path: P,
+        n_features: usize,
+        n_targets: usize,
+        has_header: bool,
+    ) -> io::Result<Dataset> {
+        Dataset::from_csv_reader(BufReader::new(File::open(path)?), n_features, n_targets, has_header)
+    }
+
+    // parses CSV rows of exactly n_features + n_targets numbers. with has_header
+    // the first line is skipped whatever it holds, blank lines are ignored, and
+    // anything else malformed is an InvalidData error naming the line
+    pub fn from_csv_reader<R: BufRead>(
+        reader: R,
+        n_features: usize,
+        n_targets: usize,
+        has_header: bool,
+    ) -> io::Result<Dataset> {
+        let n_cols = n_features + n_targets;
+        let mut examples = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if (has_header && i == 0) || line.trim().is_empty() {
+                continue;
+            }
+            let row: Result<Vec<f64>, _> = line.split(',').map(|field| field.trim().parse::<f64>()).collect();
+            let row = row.map_err(|err| invalid(format!("line {}: {}: {:?}", i + 1, err, line)))?;
+            if row.len() != n_cols {
+                return Err(invalid(format!(
+                    "line {}: expected {} columns ({} features + {} targets), found {}",
+                    i + 1, n_cols, n_features, n_targets, row.len()
+                )));
+            }
+            let targets = row[n_features..].to_vec();
+            let mut inputs = row;
+            inputs.truncate(n_features);
+            examples.push((inputs, targets));
+        }
+        Ok(Dataset::new(examples))
+    }
+
+    // standardizes every input feature to zero mean and unit variance in place,
+    // returning the (mean, std) per feature. a feature with zero variance is only
+    // centered, and its std is reported as 1.0 so the returned stats can be passed
+    // straight to `standardize` for test data
+    pub fn normalize(&mut self) -> (Vec<f64>, Vec<f64>) {
+        assert!(!self.examples.is_empty(), "can't normalize an empty dataset");
+        let n = self.examples.len() as f64;
+        let n_features = self.examples[0].0.len();
+
+        let mut mean = vec![0.0; n_features];
+        for (inputs, _) in self.examples.iter() {
+            assert_eq!(inputs.len(), n_features, "examples have different numbers of features");
+            for (m, x) in mean.iter_mut().zip(inputs.iter()) {
+                *m += x / n;
+            }
+        }
+
+        let mut var = vec![0.0; n_features];
+        for (inputs, _) in self.examples.iter() {
+            for ((v, x), m) in var.iter_mut().zip(inputs.iter()).zip(mean.iter()) {
+                *v += (x - m) * (x - m) / n;
+            }
+        }
+        let std: Vec<f64> = var.iter()
+            .map(|&v| if v > 0.0 { v.sqrt() } else { 1.0 })
+            .collect();
+
+        self.standardize(&mean, &std);
+        (mean, std)
+    }
+
+    // applies (x - mean) / std to every input feature, e.g. with the stats
+    // normalize returned for the training set
+    pub fn standardize(&mut self, mean: &[f64], std: &[f64]) {
+        assert_eq!(mean.len(), std.len(), "need one std per mean");
+        for (inputs, _) in self.examples.iter_mut() {
+            assert_eq!(inputs.len(), mean.len(), "stats don't match the number of features");
+            for ((x, m), s) in inputs.iter_mut().zip(mean.iter()).zip(std.iter()) {
+                *x = (*x - m) / s;
+            }
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl From<Vec<(Vec<f64>, Vec<f64>)>> for Dataset {
+    fn from(examples: Vec<(Vec<f64>, Vec<f64>)>) -> Dataset {
+        Dataset::new(examples)
+    }
+}
+
+impl Deref for Dataset {
+    type Target = [(Vec<f64>, Vec<f64>)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.examples
+    }
+}
+
+// ============= DATASETS =============
+// two interleaved spirals with n_points examples each, labelled 0.0 and 1.0
+pub fn spiral(n_points: usize, noise: f64, rng: &mut impl Rng) -> Vec<(Vec<f64>, Vec<f64>)> {
+    let mut data = Vec::new();
+    for i in 0..n_points {
+        let r = i as f64 / n_points as f64;
+        let t = i as f64 * 4.0;
+        
+        // First spiral (class 0)
+        let x1 = r * (t).cos() + rng.gen_range(-noise..noise);
+        let y1 = r * (t).sin() + rng.gen_range(-noise..noise);
+        data.push((vec![x1, y1], vec![0.0]));
+        
+        // Second spiral (class 1)
+        let x2 = r * (t + std::f64::consts::PI).cos() + rng.gen_range(-noise..noise);
+        let y2 = r * (t + std::f64::consts::PI).sin() + rng.gen_range(-noise..noise);
+        data.push((vec![x2, y2], vec![1.0]));
+    }
+    data
+}
+
+// ============= AUGMENTATION =============
+// mixup: blends two (inputs, targets) examples, lambda * a + (1 - lambda) * b
+pub fn mixup(a: &(Vec<f64>, Vec<f64>), b: &(Vec<f64>, Vec<f64>), lambda: f64) -> (Vec<f64>, Vec<f64>) {
+    assert_eq!(a.0.len(), b.0.len(), "mixup: inputs have different lengths");
+    assert_eq!(a.1.len(), b.1.len(), "mixup: targets have different lengths");
+
+    let blend = |x: &[f64], y: &[f64]| -> Vec<f64> {
+        x.iter().zip(y.iter()).map(|(x, y)| lambda * x + (1.0 - lambda) * y).collect()
+    };
+    (blend(&a.0, &b.0), blend(&a.1, &b.1))
+}
+
+// ============= DATA LOADER =============
+// one batch from a DataLoader, tagged with the epoch it belongs to
+#[derive(Debug, Clone)]
+pub struct Batch<'a> {
+    pub examples: Vec<&'a (Vec<f64>, Vec<f64>)>,
+    // counts from 0
+    pub epoch: usize,
+    // true for the last batch of its epoch
+    pub end_of_epoch: bool,
+}
+
+// endless iterator of batches over a dataset. every epoch visits each example
+// exactly once in a fresh shuffled order; batches never straddle two epochs,
+// so the last one of an epoch may be smaller than batch_size
+#[derive(Debug, Clone)]
+pub struct DataLoader<'a> {
+    dataset: &'a [(Vec<f64>, Vec<f64>)],
+    batch_size: usize,
+    rng: StdRng,
+    order: Vec<usize>,
+    pos: usize,
+    epoch: usize,
+}
+
+impl<'a> DataLoader<'a> {
+    pub fn new(dataset: &'a [(Vec<f64>, Vec<f64>)], batch_size: usize, rng: StdRng) -> DataLoader<'a> {
+        assert!(!dataset.is_empty(), "DataLoader needs a non-empty dataset");
+        assert!(batch_size > 0, "batch_size must be at least 1");
+        let mut loader = DataLoader {
+            dataset,
+            batch_size,
+            rng,
+            order: (0..dataset.len()).collect(),
+            pos: 0,
+            epoch: 0,
+        };
+        loader.order.shuffle(&mut loader.rng);
+        loader
+    }
+
+    // the epoch the next batch will come from
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    // batches per epoch, counting a short final one
+    pub fn batches_per_epoch(&self) -> usize {
+        self.dataset.len().div_ceil(self.batch_size)
+    }
+}
+
+impl<'a> Iterator for DataLoader<'a> {
+    type Item = Batch<'a>;
+
+    fn next(&mut self) -> Option<Batch<'a>> {
+        let end = (self.pos + self.batch_size).min(self.order.len());
+        let examples = self.order[self.pos..end].iter().map(|&i| &self.dataset[i]).collect();
+        let batch = Batch {
+            examples,
+            epoch: self.epoch,
+            end_of_epoch: end == self.order.len(),
+        };
+
+        self.pos = end;
+        if batch.end_of_epoch {
+            self.pos = 0;
+            self.epoch += 1;
+            self.order.shuffle(&mut self.rng);
+        }
+        Some(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn mixup_at_half_is_the_midpoint() {
+        let a = (vec![0.0, 2.0], vec![1.0]);
+        let b = (vec![4.0, -2.0], vec![0.0]);
+        assert_eq!(mixup(&a, &b, 0.5), (vec![2.0, 0.0], vec![0.5]));
+        assert_eq!(mixup(&a, &b, 1.0), a);
+    }
+
+    #[test]
+    fn data_loader_covers_every_example_once_per_epoch_and_reshuffles() {
+        use rand::SeedableRng;
+        let data: Vec<(Vec<f64>, Vec<f64>)> = (0..10).map(|i| (vec![i as f64], vec![0.0])).collect();
+        let mut loader = DataLoader::new(&data, 4, StdRng::seed_from_u64(3));
+        assert_eq!(loader.batches_per_epoch(), 3);
+
+        let mut orders = Vec::new();
+        for epoch in 0..3 {
+            let mut seen = Vec::new();
+            for (i, batch) in loader.by_ref().take(3).enumerate() {
+                assert_eq!(batch.epoch, epoch);
+                assert_eq!(batch.end_of_epoch, i == 2);
+                assert_eq!(batch.examples.len(), if i == 2 { 2 } else { 4 });
+                seen.extend(batch.examples.iter().map(|(x, _)| x[0]));
+            }
+            orders.push(seen.clone());
+            seen.sort_by(f64::total_cmp);
+            assert_eq!(seen, (0..10).map(|i| i as f64).collect::<Vec<f64>>());
+        }
+        assert_eq!(loader.epoch(), 3);
+        assert!(orders[0] != orders[1] || orders[1] != orders[2]);
+    }
+
+    #[test]
+    fn normalize_gives_zero_mean_and_unit_variance() {
+        let mut data = Dataset::new(vec![
+            (vec![1.0, 5.0], vec![0.0]),
+            (vec![3.0, 5.0], vec![1.0]),
+            (vec![8.0, 5.0], vec![0.0]),
+        ]);
+        let (mean, std) = data.normalize();
+        assert_eq!(mean[1], 5.0);
+        assert_eq!(std[1], 1.0);
+
+        let n = data.len() as f64;
+        let feature = |j: usize| data.iter().map(move |(x, _)| x[j]);
+        let m: f64 = feature(0).sum::<f64>() / n;
+        let var: f64 = feature(0).map(|x| (x - m) * (x - m)).sum::<f64>() / n;
+        assert!(m.abs() < 1e-12, "{}", m);
+        assert!((var - 1.0).abs() < 1e-12, "{}", var);
+        // the constant feature is only centered, targets are untouched
+        assert!(feature(1).all(|x| x == 0.0));
+        assert_eq!(data[1].1, vec![1.0]);
+    }
+
+    fn parse(csv: &str, has_header: bool) -> io::Result<Dataset> {
+        Dataset::from_csv_reader(Cursor::new(csv), 2, 1, has_header)
+    }
+
+    #[test]
+    fn from_csv_reader_skips_a_header_and_blank_lines() {
+        let data = parse("x,y,label\n0.5, -1.0, 1\n\n2,3,0\n", true).unwrap();
+        assert_eq!(data.examples, vec![
+            (vec![0.5, -1.0], vec![1.0]),
+            (vec![2.0, 3.0], vec![0.0]),
+        ]);
+        assert_eq!(parse("1,2,3\n", false).unwrap().len(), 1);
+        // a header is skipped even if a column name looks like a number
+        assert_eq!(parse("x,2024,label\n1,2,3\n", true).unwrap().examples, vec![(vec![1.0, 2.0], vec![3.0])]);
+    }
+
+    #[test]
+    fn from_csv_reader_rejects_malformed_lines() {
+        let err = parse("1.0,,0\n2,3,0\n", false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 1:"), "{}", err);
+
+        // without has_header a header line is just a malformed row
+        assert!(parse("x,y,label\n1,2,3\n", false).is_err());
+
+        let err = parse("x,y,label\n1,2\n", true).unwrap_err();
+        assert!(err.to_string().contains("line 2: expected 3 columns"), "{}", err);
+    }
+}
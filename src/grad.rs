@@ -1,17 +1,106 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::iter::{Product, Sum};
 use std::ops::{Add, Mul, Sub};
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
 use rand::Rng;
 
+pub use crate::activations::Activation;
 
-#[derive(Debug, Clone)]
+
+#[derive(Debug, Clone, Copy)]
 enum Op {
     None,
     Add,
+    Sub,
     Mul,
-    Tanh
+    MulAdd,
+    Tanh,
+    TanhFast,
+    Exp,
+    Ln,
+    Softplus,
+    Relu,
+    Abs,
+    Powf(f64),
+    Powi(i32),
+    Pow,
+    Clamp(f64, f64),
+    ScaleGrad(f64),
+}
+
+impl Op {
+    fn name(&self) -> &'static str {
+        match self {
+            Op::None => "value",
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mul => "mul",
+            Op::MulAdd => "mul_add",
+            Op::Tanh => "tanh",
+            Op::TanhFast => "tanh_fast",
+            Op::Exp => "exp",
+            Op::Ln => "ln",
+            Op::Softplus => "softplus",
+            Op::Relu => "relu",
+            Op::Abs => "abs",
+            Op::Powf(_) => "powf",
+            Op::Powi(_) => "powi",
+            Op::Pow => "pow",
+            Op::Clamp(..) => "clamp",
+            Op::ScaleGrad(_) => "scale_grad",
+        }
+    }
+}
+
+// gradient generations: a stored grad only counts if it was written during the
+// current generation, anything older reads as 0.0. bumping the generation is
+// therefore an O(1) way to zero every gradient in the graph at once.
+// invariant: grads are only ever read through Param::grad and written through
+// Param::set_grad, which stamp and check the generation; touching the raw
+// field anywhere else would resurrect stale values.
+// the counter is thread-local, like the Rc-based nodes it applies to, so it is
+// shared by every graph on the thread: bumping it to reset one model also zeroes
+// the grads of any other model living on the same thread
+thread_local! {
+    static GENERATION: Cell<u64> = const { Cell::new(0) };
+}
+
+fn current_generation() -> u64 {
+    GENERATION.with(|g| g.get())
+}
+
+// Node::exp clamps its input to [-EXP_CLAMP, EXP_CLAMP]; e^50 is about 5e21
+pub const EXP_CLAMP: f64 = 50.0;
+
+// lazily zeroes the gradients of every node on this thread, in every model
+pub fn next_generation() {
+    GENERATION.with(|g| g.set(g.get() + 1));
+}
+
+// where the graph's debug warnings go. stderr by default; per thread, like
+// the nodes whose misuse it reports
+type WarningHook = Box<dyn Fn(&str)>;
+
+thread_local! {
+    static WARNING_HOOK: RefCell<WarningHook> =
+        RefCell::new(Box::new(|msg| eprintln!("warning: {}", msg)));
 }
 
+// replaces this thread's warning hook, e.g. to collect warnings or silence them
+pub fn set_warning_hook(hook: impl Fn(&str) + 'static) {
+    WARNING_HOOK.with(|h| *h.borrow_mut() = Box::new(hook));
+}
+
+fn warn(msg: &str) {
+    WARNING_HOOK.with(|h| (h.borrow())(msg));
+}
+
+// (value, grad, op name) copied out of a node at one point in time
+pub type Snapshot = (f64, f64, &'static str);
+
 // param contains the values inside a node
 // nodes need to be used by multiple 
 #[derive(Debug, Clone)]
@@ -21,8 +110,28 @@ pub struct Node(Rc<RefCell<Param>>);
 struct Param {
     val: f64,
     grad: f64,
+    // generation in which grad was last written
+    generation: u64,
     children: Vec<Node>,
     op: Op,
+    // optional label for debugging output
+    name: Option<String>,
+    // constants never receive gradients
+    constant: bool,
+    // generation of the last backward pass that reached this node, cleared
+    // whenever the grad is set from outside. backs Node::try_grad
+    backward_generation: Option<u64>,
+}
+
+impl Param {
+    fn grad(&self) -> f64 {
+        if self.generation == current_generation() { self.grad } else { 0.0 }
+    }
+
+    fn set_grad(&mut self, grad: f64) {
+        self.grad = grad;
+        self.generation = current_generation();
+    }
 }
 
 impl Node {
@@ -30,79 +139,597 @@ impl Node {
         Node(Rc::new(RefCell::new(Param {
             val,
             grad: 0.0,
+            generation: current_generation(),
             children: Vec::new(),
-            op: Op::None
+            op: Op::None,
+            name: None,
+            constant: false,
+            backward_generation: None,
         })))
     }
 
+    // a leaf that backprop never writes a gradient into, for fixed values
+    // like targets and coefficients that are part of the graph but not learned
+    pub fn constant(val: f64) -> Self {
+        let node = Node::new(val);
+        node.0.borrow_mut().constant = true;
+        node
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.0.borrow().constant
+    }
+
+    // handles to the direct inputs of the op that produced this node,
+    // empty for leaves
+    pub fn children(&self) -> Vec<Node> {
+        self.0.borrow().children.clone()
+    }
+
+    // true if both handles point at the same underlying node
+    pub fn ptr_eq(&self, other: &Node) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub fn with_name(self, name: &str) -> Node {
+        self.0.borrow_mut().name = Some(name.to_string());
+        self
+    }
+
+    // the label given with with_name, or the name of the op that made the node
+    pub fn name(&self) -> String {
+        let node = self.0.borrow();
+        match &node.name {
+            Some(name) => name.clone(),
+            None => node.op.name().to_string(),
+        }
+    }
+
+    fn from_op(val: f64, children: Vec<Node>, op: Op) -> Node {
+        let result = Node::new(val);
+        result.0.borrow_mut().children = children;
+        result.0.borrow_mut().op = op;
+        result
+    }
+
     pub fn val(&self) -> f64 {
         self.0.borrow().val
     }
     pub fn grad(&self) -> f64 {
-        self.0.borrow().grad
+        self.0.borrow().grad()
     }
     pub fn set_grad(&self, grad: f64) {
-        self.0.borrow_mut().grad = grad;
+        let mut node = self.0.borrow_mut();
+        node.set_grad(grad);
+        node.backward_generation = None;
+    }
+
+    // the grad, or None if no backward pass has reached this node since its
+    // grad was last set or zeroed (or since next_generation). catches reading
+    // a grad that backward never wrote
+    pub fn try_grad(&self) -> Option<f64> {
+        let node = self.0.borrow();
+        (node.backward_generation == Some(current_generation())).then(|| node.grad())
+    }
+    // adds g to the current grad; the read-modify-write happens under one
+    // borrow, so a node appearing twice among the children (x * x) is fine.
+    // does nothing on constants, which is how backprop skips them
+    pub fn accumulate_grad(&self, g: f64) {
+        let mut node = self.0.borrow_mut();
+        if node.constant {
+            return;
+        }
+        let grad = node.grad();
+        node.set_grad(grad + g);
+    }
+
+    // both at once under a single borrow
+    pub fn value_and_grad(&self) -> (f64, f64) {
+        let node = self.0.borrow();
+        (node.val, node.grad())
+    }
+    // overwrites the value in place; nodes computed from this one are not updated
+    pub fn set_val(&self, val: f64) {
+        self.0.borrow_mut().val = val;
+    }
+
+    // for outputs bounded by [-1, 1] like tanh: true once |val| > threshold,
+    // where the derivative is close to zero and learning stalls
+    pub fn is_saturated(&self, threshold: f64) -> bool {
+        self.val().abs() > threshold
+    }
+
+    // the same for any bounded output: true once val is within `margin` of
+    // min or max, e.g. is_saturated_in(0.0, 1.0, 0.01) for a sigmoid
+    pub fn is_saturated_in(&self, min: f64, max: f64, margin: f64) -> bool {
+        let val = self.val();
+        val < min + margin || val > max - margin
     }
 
     pub fn tanh(&self) -> Node {
-        let result = Node::new(self.val().tanh());
-        result.0.borrow_mut().children.push(self.clone());
-        result.0.borrow_mut().op = Op::Tanh;
-        result
+        Node::from_op(self.val().tanh(), vec![self.clone()], Op::Tanh)
+    }
+
+    // approximate tanh for cheaper inference: the rational x(27 + x^2) / (27 + 9x^2),
+    // which reaches exactly +-1 at |x| = 3 and is held there beyond. within 0.024
+    // of tanh everywhere. the backward pass uses 1 - y^2 of the approximate
+    // output y, which is close enough to train with
+    pub fn tanh_fast(&self) -> Node {
+        let x = self.val();
+        let val = if x.abs() >= 3.0 {
+            x.signum()
+        } else {
+            let x2 = x * x;
+            x * (27.0 + x2) / (27.0 + 9.0 * x2)
+        };
+        Node::from_op(val, vec![self.clone()], Op::TanhFast)
     }
 
     pub fn square(&self) -> Node {
         self.clone() * self.clone()
     }
 
+    // the input is clamped to [-EXP_CLAMP, EXP_CLAMP] first so huge logits give
+    // a large finite value instead of inf. outside that range the gradient is 0
+    pub fn exp(&self) -> Node {
+        let x = self.val().clamp(-EXP_CLAMP, EXP_CLAMP);
+        Node::from_op(x.exp(), vec![self.clone()], Op::Exp)
+    }
+
+    pub fn ln(&self) -> Node {
+        Node::from_op(self.val().ln(), vec![self.clone()], Op::Ln)
+    }
+
+    // max(x, 0). the gradient at exactly 0 is taken to be 0
+    pub fn relu(&self) -> Node {
+        Node::from_op(self.val().max(0.0), vec![self.clone()], Op::Relu)
+    }
+
+    // min(max(x, 0), 6), as a clamp: gradient 1 inside [0, 6], 0 outside
+    pub fn relu6(&self) -> Node {
+        self.clamp(0.0, 6.0)
+    }
+
+    // |x|, with a subgradient of 0 at exactly 0
+    pub fn abs(&self) -> Node {
+        Node::from_op(self.val().abs(), vec![self.clone()], Op::Abs)
+    }
+
+    // ln(1 + e^x), evaluated as max(x, 0) + ln(1 + e^-|x|) so large inputs
+    // don't overflow. the gradient is sigmoid(x)
+    pub fn softplus(&self) -> Node {
+        let x = self.val();
+        let val = x.max(0.0) + (-x.abs()).exp().ln_1p();
+        Node::from_op(val, vec![self.clone()], Op::Softplus)
+    }
+
+    pub fn powf(&self, exp: f64) -> Node {
+        Node::from_op(self.val().powf(exp), vec![self.clone()], Op::Powf(exp))
+    }
+
+    // integer power via f64::powi. n = 0 gives a constant 1.0, so no grad
+    // flows back; negative n works as long as self isn't 0
+    pub fn powi(&self, n: i32) -> Node {
+        if n == 0 {
+            return Node::constant(1.0);
+        }
+        Node::from_op(self.val().powi(n), vec![self.clone()], Op::Powi(n))
+    }
+
+    // self^exp with a differentiable exponent. only defined for a positive
+    // base, where d/d exp is self^exp * ln(self). a node of its own rather
+    // than exp(exp * ln(self)), which would run into the exp clamp
+    pub fn pow(&self, exp: &Node) -> Node {
+        assert!(self.val() > 0.0, "pow needs a positive base, got {}", self.val());
+        Node::from_op(self.val().powf(exp.val()), vec![self.clone(), exp.clone()], Op::Pow)
+    }
+
+    pub fn sqrt(&self) -> Node {
+        self.powf(0.5)
+    }
+
+    pub fn recip(&self) -> Node {
+        self.powf(-1.0)
+    }
+
+    // self * a + b as a single node
+    pub fn mul_add(&self, a: &Node, b: &Node) -> Node {
+        let val = self.val() * a.val() + b.val();
+        Node::from_op(val, vec![self.clone(), a.clone(), b.clone()], Op::MulAdd)
+    }
+
+    // gradient passes through inside [min, max] and is zero where the value was clipped
+    pub fn clamp(&self, min: f64, max: f64) -> Node {
+        Node::from_op(self.val().clamp(min, max), vec![self.clone()], Op::Clamp(min, max))
+    }
+
+    // clamp to [-1, 1], a cheap bounded alternative to tanh
+    pub fn hardtanh(&self) -> Node {
+        self.clamp(-1.0, 1.0)
+    }
+
+    // -1.0, 0.0 or 1.0 as a fresh constant. not differentiable: the result has no
+    // children, so it acts as a stop-gradient and nothing flows back into self
+    pub fn sign(&self) -> Node {
+        let val = self.val();
+        Node::constant(if val > 0.0 { 1.0 } else if val < 0.0 { -1.0 } else { 0.0 })
+    }
+
+    // identity in the forward pass, multiplies the gradient by `factor` on the
+    // way back (factor = -1.0 gives a gradient reversal layer)
+    pub fn scale_grad(&self, factor: f64) -> Node {
+        Node::from_op(self.val(), vec![self.clone()], Op::ScaleGrad(factor))
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let node = self.0.borrow();
+        (node.val, node.grad(), node.op.name())
+    }
+
+    // the computation as an infix expression, e.g. tanh((w0 * x0) + b).
+    // named nodes print as their name, unnamed leaves as their value.
+    // shared subexpressions are repeated at every use rather than bound
+    // once, and the recursion makes this meant for small graphs
+    pub fn to_expr(&self) -> String {
+        self.expr(true)
+    }
+
+    // `top` drops the parentheses around a binary op when it's the whole
+    // expression or a function argument
+    fn expr(&self, top: bool) -> String {
+        let node = self.0.borrow();
+        if let Some(name) = &node.name {
+            return name.clone();
+        }
+        let arg = |i: usize| node.children[i].expr(true);
+        let operand = |i: usize| node.children[i].expr(false);
+        let binary = |s: String| if top { s } else { format!("({})", s) };
+
+        match node.op {
+            Op::None => format!("{}", node.val),
+            Op::Add => binary(format!("{} + {}", operand(0), operand(1))),
+            Op::Sub => binary(format!("{} - {}", operand(0), operand(1))),
+            Op::Mul => binary(format!("{} * {}", operand(0), operand(1))),
+            Op::MulAdd => binary(format!("({} * {}) + {}", operand(0), operand(1), operand(2))),
+            Op::Powf(exp) => binary(format!("{}^{}", operand(0), exp)),
+            Op::Powi(n) => binary(format!("{}^{}", operand(0), n)),
+            Op::Pow => binary(format!("{}^{}", operand(0), operand(1))),
+            Op::Clamp(min, max) => format!("clamp({}, {}, {})", arg(0), min, max),
+            Op::ScaleGrad(factor) => format!("scale_grad({}, {})", arg(0), factor),
+            op => format!("{}({})", op.name(), arg(0)),
+        }
+    }
+
+    // calls f once on every distinct node reachable from self, children
+    // before parents and self last. the traversal behind graph_size and
+    // snapshot_graph, for writing other whole-graph passes
+    pub fn visit<F: FnMut(&Node)>(&self, mut f: F) {
+        for node in self.topological_order().iter() {
+            f(node);
+        }
+    }
+
+    // number of distinct nodes reachable from self, including self
+    pub fn graph_size(&self) -> usize {
+        let mut size = 0;
+        self.visit(|_| size += 1);
+        size
+    }
+
+    // snapshots of every node in the graph, children before parents
+    pub fn snapshot_graph(&self) -> Vec<Snapshot> {
+        let mut snapshots = Vec::new();
+        self.visit(|node| snapshots.push(node.snapshot()));
+        snapshots
+    }
+
+    // copies the graph under self into fresh nodes with the same values, ops,
+    // names and constant flags, and zero grads. a node reachable along several
+    // paths is copied once, so shared subgraphs stay shared in the copy
+    pub fn deep_copy(&self) -> Node {
+        let mut copies: HashMap<*const RefCell<Param>, Node> = HashMap::new();
+        for node in self.topological_order().iter() {
+            let children = node.0.borrow().children.iter()
+                .map(|child| copies[&Rc::as_ptr(&child.0)].clone())
+                .collect();
+            copies.insert(Rc::as_ptr(&node.0), node.rebuilt(children));
+        }
+        copies.remove(&Rc::as_ptr(&self.0)).expect("topological_order always includes self")
+    }
+
+    // a copy of the graph under self in which every subgraph computed purely
+    // from constants is collapsed into a single constant holding its value,
+    // e.g. (2 * 3) + x becomes 6 + x. leaves that aren't constants, like inputs
+    // and parameters, are kept as the same nodes so gradients still reach them,
+    // and so is any part of the graph with nothing to fold
+    pub fn fold_constants(&self) -> Node {
+        let mut folded: HashMap<*const RefCell<Param>, Node> = HashMap::new();
+        for node in self.topological_order().iter() {
+            let children: Vec<Node> = node.0.borrow().children.iter()
+                .map(|child| folded[&Rc::as_ptr(&child.0)].clone())
+                .collect();
+
+            let result = if children.is_empty() {
+                node.clone()
+            } else if children.iter().all(|child| child.is_constant()) {
+                Node::constant(node.val())
+            } else if children.iter().zip(node.0.borrow().children.iter()).all(|(new, old)| new.ptr_eq(old)) {
+                node.clone()
+            } else {
+                node.rebuilt(children)
+            };
+            folded.insert(Rc::as_ptr(&node.0), result);
+        }
+        folded.remove(&Rc::as_ptr(&self.0)).expect("topological_order always includes self")
+    }
+
+    // a fresh node with self's value, op, name and constant flag over new
+    // children, with a zero grad
+    fn rebuilt(&self, children: Vec<Node>) -> Node {
+        let param = self.0.borrow();
+        Node(Rc::new(RefCell::new(Param {
+            val: param.val,
+            grad: 0.0,
+            generation: current_generation(),
+            children,
+            op: param.op,
+            name: param.name.clone(),
+            constant: param.constant,
+            backward_generation: None,
+        })))
+    }
+
+    // every node reachable from self, children before parents,
+    // with shared nodes appearing once. self is always last
+    pub fn topological_order(&self) -> Vec<Node> {
+        let mut order = Vec::new();
+        let mut visited = HashSet::new();
+        // (node, children already pushed) - iterative so long chains don't blow the stack
+        let mut stack = vec![(self.clone(), false)];
+
+        while let Some((node, expanded)) = stack.pop() {
+            if expanded {
+                order.push(node);
+                continue;
+            }
+            if !visited.insert(Rc::as_ptr(&node.0)) {
+                continue;
+            }
+            stack.push((node.clone(), true));
+            for child in node.0.borrow().children.iter().rev() {
+                if !visited.contains(&Rc::as_ptr(&child.0)) {
+                    stack.push((child.clone(), false));
+                }
+            }
+        }
+        order
+    }
+
+    // propagates self's grad (seed it first) to everything it depends on.
+    // grads add up: a node used in several places, like a weight shared by
+    // every example of a batch loss or x in x * x, ends up with the sum of the
+    // gradients from each use. leaves keep accumulating across passes until
+    // they're zeroed, which is what makes gradient accumulation work
     pub fn backward_pass(&self) {
-        // immutable borrow for getting grad
+        self.backward_pass_ordered(&self.topological_order());
+    }
+
+    // backward_pass over an order precomputed with topological_order, so a
+    // graph that doesn't change shape only has to be sorted once. the order
+    // must come from this node's graph and be current
+    pub fn backward_pass_ordered(&self, order: &[Node]) {
+        debug_assert!(
+            self.grad() != 0.0,
+            "backward_pass called on a node with zero grad; seed the root with set_grad(1.0) first"
+        );
+        debug_assert!(
+            order.last().is_some_and(|last| last.ptr_eq(self)),
+            "backward_pass_ordered needs an order from this node's topological_order"
+        );
+        if cfg!(debug_assertions) {
+            let stale = self.stale_grad_count(order);
+            if stale > 0 {
+                warn(&format!(
+                    "backward_pass found {} intermediate node(s) with grads left over from an earlier pass; \
+                     call next_generation() or zero the graph's grads before running it again",
+                    stale
+                ));
+            }
+        }
+        // each node pushes its grad to its children only once all of its
+        // parents have contributed, so shared nodes accumulate correctly
+        let generation = current_generation();
+        for node in order.iter().rev() {
+            node.0.borrow_mut().backward_generation = Some(generation);
+            node.backward_step();
+        }
+    }
+
+    // intermediate nodes in `order`, other than self, still holding grads from
+    // an earlier pass; backward would propagate those a second time. leaves are
+    // left out since accumulating into parameters across passes is legitimate
+    fn stale_grad_count(&self, order: &[Node]) -> usize {
+        order.iter()
+            .filter(|node| !node.ptr_eq(self))
+            .filter(|node| !node.0.borrow().children.is_empty() && node.grad() != 0.0)
+            .count()
+    }
+
+    // propagates this node's grad one level down to its children
+    fn backward_step(&self) {
         let node = self.0.borrow();
+        let grad = node.grad();
         
         match node.op {
             Op::Add => {
-                let grad = node.grad;
-                drop(node);
-                for child in &self.0.borrow().children {
-                    let old_grad = child.grad();
-                    child.set_grad(old_grad + grad); 
-                    // mutable borrow for modifying children
+                for child in &node.children {
+                    child.accumulate_grad(grad);
                 }
             }
+            Op::Sub => {
+                node.children[0].accumulate_grad(grad);
+                node.children[1].accumulate_grad(-grad);
+            }
             Op::Mul => {
-                if self.0.borrow().children.len() == 2 {
-                    let grad = node.grad;
-                    let val0 = self.0.borrow().children[0].val();
-                    let val1 = self.0.borrow().children[1].val();
-                    drop(node);
-                    
-                    let old_grad0 = self.0.borrow().children[0].grad();
-                    let old_grad1 = self.0.borrow().children[1].grad();
-                    
-                    self.0.borrow().children[0].set_grad(old_grad0 + val1 * grad);
-                    self.0.borrow().children[1].set_grad(old_grad1 + val0 * grad);
-                    
-                }
+                let val0 = node.children[0].val();
+                let val1 = node.children[1].val();
+                
+                node.children[0].accumulate_grad(val1 * grad);
+                node.children[1].accumulate_grad(val0 * grad);
+            }
+            Op::MulAdd => {
+                let val0 = node.children[0].val();
+                let val1 = node.children[1].val();
+
+                node.children[0].accumulate_grad(val1 * grad);
+                node.children[1].accumulate_grad(val0 * grad);
+                node.children[2].accumulate_grad(grad);
             }
             Op::Tanh => {
-                if let Some(child) = self.0.borrow().children.first() {
-                    let val = self.val();
-                    let der = 1.0 - val * val;
-                    let grad = node.grad;
-                    drop(node);
-                    
-                    let old_grad = child.grad();
-                    child.set_grad(old_grad + der * grad);
+                // recomputed from the child instead of using the cached output,
+                // which goes stale if the child was changed with set_val
+                let child = &node.children[0];
+                let t = child.val().tanh();
+                child.accumulate_grad((1.0 - t * t) * grad);
+            }
+            Op::TanhFast => {
+                node.children[0].accumulate_grad((1.0 - node.val * node.val) * grad);
+            }
+            Op::Exp => {
+                // d/dx e^x is the output itself, inside the clamp range
+                let child = &node.children[0];
+                if child.val().abs() <= EXP_CLAMP {
+                    child.accumulate_grad(node.val * grad);
+                }
+            }
+            Op::Ln => {
+                let child = &node.children[0];
+                child.accumulate_grad(grad / child.val());
+            }
+            Op::Softplus => {
+                let child = &node.children[0];
+                // sigmoid, written so e^x is only taken for x < 0
+                let x = child.val();
+                let sigmoid = if x >= 0.0 {
+                    1.0 / (1.0 + (-x).exp())
+                } else {
+                    let e = x.exp();
+                    e / (1.0 + e)
+                };
+                child.accumulate_grad(sigmoid * grad);
+            }
+            Op::Relu => {
+                let child = &node.children[0];
+                if child.val() > 0.0 {
+                    child.accumulate_grad(grad);
+                }
+            }
+            Op::Abs => {
+                let child = &node.children[0];
+                let x = child.val();
+                if x != 0.0 {
+                    child.accumulate_grad(x.signum() * grad);
                 }
             }
+            Op::Powf(exp) => {
+                let child = &node.children[0];
+                let der = exp * child.val().powf(exp - 1.0);
+                child.accumulate_grad(der * grad);
+            }
+            Op::Powi(n) => {
+                let child = &node.children[0];
+                let der = n as f64 * child.val().powi(n - 1);
+                child.accumulate_grad(der * grad);
+            }
+            Op::Pow => {
+                let base = node.children[0].val();
+                let exp = node.children[1].val();
+
+                node.children[0].accumulate_grad(exp * base.powf(exp - 1.0) * grad);
+                node.children[1].accumulate_grad(node.val * base.ln() * grad);
+            }
+            Op::Clamp(min, max) => {
+                let child = &node.children[0];
+                let x = child.val();
+                if x >= min && x <= max {
+                    child.accumulate_grad(grad);
+                }
+            }
+            Op::ScaleGrad(factor) => {
+                node.children[0].accumulate_grad(factor * grad);
+            }
             Op::None => {}
         }
-        
-        // Recursively apply to children
-        for child in &self.0.borrow().children {
-            child.backward_pass();
+    }
+
+    // d self / d input as a graph of its own rather than an f64, so it can be
+    // differentiated again: run backward_pass on the result for second
+    // derivatives. mirrors backward_step op for op, building each local
+    // derivative out of nodes. inputs self doesn't depend on get a constant 0
+    pub fn grad_node(&self, input: &Node) -> Node {
+        let mut grads: HashMap<*const RefCell<Param>, Node> = HashMap::new();
+        grads.insert(Rc::as_ptr(&self.0), Node::constant(1.0));
+
+        for node in self.topological_order().iter().rev() {
+            let Some(g) = grads.get(&Rc::as_ptr(&node.0)).cloned() else {
+                continue;
+            };
+            let (op, children) = {
+                let param = node.0.borrow();
+                (param.op, param.children.clone())
+            };
+            let c = |k: f64| Node::constant(k);
+            let contributions: Vec<Node> = match op {
+                Op::Add => children.iter().map(|_| g.clone()).collect(),
+                Op::Sub => vec![g.clone(), c(-1.0) * g],
+                Op::Mul => vec![g.clone() * children[1].clone(), g * children[0].clone()],
+                Op::MulAdd => vec![
+                    g.clone() * children[1].clone(),
+                    g.clone() * children[0].clone(),
+                    g,
+                ],
+                Op::Tanh => vec![g * (c(1.0) - children[0].tanh().square())],
+                Op::TanhFast => vec![g * (c(1.0) - node.square())],
+                Op::Exp => {
+                    let inside = children[0].val().abs() <= EXP_CLAMP;
+                    vec![g * node.clone() * c(if inside { 1.0 } else { 0.0 })]
+                }
+                Op::Ln => vec![g * children[0].recip()],
+                // sigmoid(x) = e^(x - softplus(x)), which stays finite for any x
+                Op::Softplus => vec![g * (children[0].clone() - node.clone()).exp()],
+                Op::Relu => vec![g * c(if children[0].val() > 0.0 { 1.0 } else { 0.0 })],
+                Op::Abs => {
+                    let x = children[0].val();
+                    vec![g * c(if x != 0.0 { x.signum() } else { 0.0 })]
+                }
+                Op::Powf(exp) => vec![g * c(exp) * children[0].powf(exp - 1.0)],
+                Op::Powi(n) => vec![g * c(n as f64) * children[0].powi(n - 1)],
+                Op::Pow => vec![
+                    g.clone() * children[1].clone() * children[0].pow(&(children[1].clone() - c(1.0))),
+                    g * node.clone() * children[0].ln(),
+                ],
+                Op::Clamp(min, max) => {
+                    let x = children[0].val();
+                    vec![g * c(if x >= min && x <= max { 1.0 } else { 0.0 })]
+                }
+                Op::ScaleGrad(factor) => vec![g * c(factor)],
+                Op::None => Vec::new(),
+            };
+
+            for (child, contribution) in children.iter().zip(contributions) {
+                if child.is_constant() {
+                    continue;
+                }
+                let key = Rc::as_ptr(&child.0);
+                let total = match grads.remove(&key) {
+                    Some(acc) => acc + contribution,
+                    None => contribution,
+                };
+                grads.insert(key, total);
+            }
         }
+
+        grads.remove(&Rc::as_ptr(&input.0)).unwrap_or_else(|| Node::constant(0.0))
     }
 }
 
@@ -110,174 +737,2148 @@ impl Add for Node {
     type Output = Node;
 
     fn add(self, other: Self) -> Self::Output {
-        let result = Node::new(self.val() + other.val());
-        result.0.borrow_mut().children.push(self);
-        result.0.borrow_mut().children.push(other);
-        result.0.borrow_mut().op = Op::Add;
-        result
+        Node::from_op(self.val() + other.val(), vec![self, other], Op::Add)
     }
 }
 impl Mul for Node {
     type Output = Node;
 
     fn mul(self, other: Self) -> Self::Output {
-        let result = Node::new(self.val() * other.val());
-        result.0.borrow_mut().children.push(self);
-        result.0.borrow_mut().children.push(other);
-        result.0.borrow_mut().op = Op::Mul;
-        result
+        Node::from_op(self.val() * other.val(), vec![self, other], Op::Mul)
     }
 }
 impl Sub for Node {
     type Output = Node;
 
     fn sub(self, other: Self) -> Self::Output {
-        self.clone() + other.clone()*Node::new(-1.0)
+        Node::from_op(self.val() - other.val(), vec![self, other], Op::Sub)
     }
 }
 
+// a 0.0 leaf, handy as an accumulator seed
+impl Default for Node {
+    fn default() -> Node {
+        Node::new(0.0)
+    }
+}
 
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}(val={:.4}, grad={:.4})", self.name(), self.val(), self.grad())
+    }
+}
 
-#[derive(Debug, Clone)]
-pub struct Neuron {
-    n_in: i64,
-    pub w: Vec<Node>,
-    pub b: Node,
+// with the `serde` feature a node serializes as its value and name only.
+// op, children and grad are not preserved, so a deserialized node is always a
+// fresh leaf; handles that shared one node come back as independent nodes
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct NodeRepr {
+    val: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
 }
 
-impl Neuron {
-    pub fn new(n_in: i64) -> Self {
-        let mut rng = rand::thread_rng();
-        
-        // Initialize with smaller weights to prevent saturation
-        let w = (0..n_in)
-            .map(|_| Node::new(rng.gen_range(-0.1..0.1)))
-            .collect();
-            
-        let b = Node::new(rng.gen_range(-0.1..0.1));
-        
-        Neuron { n_in, w, b }
+#[cfg(feature = "serde")]
+impl serde::Serialize for Node {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let node = self.0.borrow();
+        NodeRepr { val: node.val, name: node.name.clone() }.serialize(serializer)
     }
+}
 
-    pub fn forward(&self, x: Vec<Node>) -> Node {
-        let mut act = self.b.clone();
-        
-        for i in 0..self.n_in as usize {
-            let weight = self.w[i].clone();
-            let input = x[i].clone();
-            let weighted_input = weight * input;
-            act = act + weighted_input;
-        }
-        
-        act.tanh()
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Node {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Node, D::Error> {
+        let repr = NodeRepr::deserialize(deserializer)?;
+        let node = Node::new(repr.val);
+        node.0.borrow_mut().name = repr.name;
+        Ok(node)
     }
+}
 
-    pub fn update_params(&self, learning_rate: f64) {
-        // Add gradient clipping
-        let clip_value = 1.0;
-        
-        for w in &self.w {
-            let grad = w.grad().clamp(-clip_value, clip_value);
-            let mut node = w.0.borrow_mut();
-            node.val -= learning_rate * grad;
-        }
-        
-        let grad = self.b.grad().clamp(-clip_value, clip_value);
-        let mut b = self.b.0.borrow_mut();
-        b.val -= learning_rate * grad;
+// folds with Add starting from a zero constant
+impl Sum<Node> for Node {
+    fn sum<I: Iterator<Item = Node>>(iter: I) -> Node {
+        iter.fold(Node::constant(0.0), |acc, n| acc + n)
+    }
+}
+impl<'a> Sum<&'a Node> for Node {
+    fn sum<I: Iterator<Item = &'a Node>>(iter: I) -> Node {
+        iter.cloned().sum()
     }
+}
 
-    pub fn zero_grad(&self) {
-        for w in &self.w {
-            w.set_grad(0.0);
-        }
-        self.b.set_grad(0.0);
+// folds with Mul starting from a constant 1.0
+impl Product<Node> for Node {
+    fn product<I: Iterator<Item = Node>>(iter: I) -> Node {
+        iter.fold(Node::constant(1.0), |acc, n| acc * n)
+    }
+}
+impl<'a> Product<&'a Node> for Node {
+    fn product<I: Iterator<Item = &'a Node>>(iter: I) -> Node {
+        iter.cloned().product()
     }
+}
 
+// sum of elementwise products, built from graph ops so grads flow into both sides
+pub fn dot(a: &[Node], b: &[Node]) -> Node {
+    assert_eq!(a.len(), b.len(), "dot: length mismatch");
+    a.iter()
+        .zip(b.iter())
+        .fold(Node::constant(0.0), |acc, (x, y)| acc + x.clone() * y.clone())
 }
 
-// ============= LAYER =============
-#[derive(Debug, Clone)]
-pub struct Layer{
-    n_in: i64,
-    n_out: i64,
-    neurons: Vec<Neuron>
+// elementwise vector math on slices of nodes; every result is built from
+// graph ops so gradients flow back into the inputs
+pub trait NodeVecOps {
+    fn add_scalar(&self, c: f64) -> Vec<Node>;
+    fn mul_scalar(&self, c: f64) -> Vec<Node>;
+    fn elementwise_add(&self, other: &[Node]) -> Vec<Node>;
+    fn elementwise_mul(&self, other: &[Node]) -> Vec<Node>;
+    fn l2_norm(&self) -> Node;
 }
-impl Layer {
-    pub fn new(n_in: i64, n_out: i64) -> Layer{
-        let mut neurons: Vec<Neuron> = Vec::new();
-        for i in 1..=n_out {
-            neurons.push(Neuron::new(n_in));
-        }
 
-        Layer{
-            n_in: n_in,
-            n_out: n_out,
-            neurons: neurons
-        }
+impl NodeVecOps for [Node] {
+    fn add_scalar(&self, c: f64) -> Vec<Node> {
+        self.iter().map(|x| x.clone() + Node::constant(c)).collect()
     }
 
-    pub fn forward(&mut self, x: Vec<Node>) -> Vec<Node> {
-        let mut outputs: Vec<Node> = vec![];
-        for i in 0..self.n_out as usize {
-            outputs.push(self.neurons[i].forward(x.clone()));
-        }
-        outputs
-    }    
-    
-    pub fn update_params(&mut self, step_size: f64) {
-        for neuron in self.neurons.iter_mut(){
-            neuron.update_params(step_size);
-        }
+    fn mul_scalar(&self, c: f64) -> Vec<Node> {
+        self.iter().map(|x| x.clone() * Node::constant(c)).collect()
     }
 
-    pub fn zero_grad(&mut self) {
-        for neuron in self.neurons.iter_mut(){
-            neuron.zero_grad();
-        }
+    fn elementwise_add(&self, other: &[Node]) -> Vec<Node> {
+        assert_eq!(self.len(), other.len(), "elementwise_add: length mismatch");
+        self.iter().zip(other.iter()).map(|(a, b)| a.clone() + b.clone()).collect()
     }
-}
 
+    fn elementwise_mul(&self, other: &[Node]) -> Vec<Node> {
+        assert_eq!(self.len(), other.len(), "elementwise_mul: length mismatch");
+        self.iter().zip(other.iter()).map(|(a, b)| a.clone() * b.clone()).collect()
+    }
 
-// ============= MLP =============
-#[derive(Debug, Clone)]
-pub struct MLP{
-    n_in: i64,
-    n_outs: Vec<i64>,
-    layers: Vec<Layer>
+    // note the gradient is undefined (inf) for an all-zero vector
+    fn l2_norm(&self) -> Node {
+        dot(self, self).sqrt()
+    }
 }
 
-impl MLP {
-    pub fn new(n_in: i64, n_outs: Vec<i64>) -> MLP{
-        let mut layers: Vec<Layer> = vec![Layer::new(n_in, n_outs[0])];
-        for i in 1..n_outs.len() {
-            layers.push(Layer::new(n_outs[i-1], n_outs[i]));
-        }
+// multiplies every element of v by the same scalar node; s collects the
+// gradient from every element
+pub fn scale_vector(v: &[Node], s: &Node) -> Vec<Node> {
+    v.iter().map(|x| x.clone() * s.clone()).collect()
+}
 
-        MLP{
-            n_in: n_in,
-            n_outs: n_outs,
-            layers: layers
-        }
-    }
+// normalizes logits into a probability distribution; the max is subtracted as a
+// constant first, which doesn't change the result but keeps exp from overflowing
+pub fn softmax(logits: &[Node]) -> Vec<Node> {
+    let max = logits.iter().map(|l| l.val()).fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<Node> = logits.iter()
+        .map(|l| (l.clone() - Node::constant(max)).exp())
+        .collect();
+    let inv_total = exps.iter()
+        .fold(Node::constant(0.0), |acc, e| acc + e.clone())
+        .recip();
+    exps.into_iter().map(|e| e * inv_total.clone()).collect()
+}
 
-    pub fn forward(&mut self, x: Vec<Node>) -> Vec<Node> {
-        let mut outputs: Vec<Node> = x;
-        for layer in self.layers.iter_mut() {
-            outputs = layer.forward(outputs);
+// ln(softmax(logits)) computed as (x - max) - ln(sum(exp(x - max))), which stays
+// finite for logits where softmax underflows to 0
+pub fn log_softmax(logits: &[Node]) -> Vec<Node> {
+    let max = logits.iter().map(|l| l.val()).fold(f64::NEG_INFINITY, f64::max);
+    let shifted: Vec<Node> = logits.iter()
+        .map(|l| l.clone() - Node::constant(max))
+        .collect();
+    let log_total = shifted.iter()
+        .fold(Node::constant(0.0), |acc, s| acc + s.exp())
+        .ln();
+    shifted.into_iter().map(|s| s - log_total.clone()).collect()
+}
+
+// after a backward pass, the k inputs with the largest |grad| as (index, grad)
+pub fn top_grad_inputs(inputs: &[Node], k: usize) -> Vec<(usize, f64)> {
+    let mut ranked: Vec<(usize, f64)> = inputs.iter()
+        .map(|input| input.grad())
+        .enumerate()
+        .collect();
+    ranked.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+    ranked.truncate(k);
+    ranked
+}
+
+// every parameter's value as one flat vector, in the order given. with
+// params_from_vec this lets external optimizers work on the model directly
+pub fn params_to_vec(params: &[Node]) -> Vec<f64> {
+    params.iter().map(|p| p.val()).collect()
+}
+
+// writes values back in the order of params_to_vec
+pub fn params_from_vec(params: &[Node], values: &[f64]) {
+    assert_eq!(
+        params.len(), values.len(),
+        "got {} values for {} parameters", values.len(), params.len()
+    );
+    for (param, &val) in params.iter().zip(values.iter()) {
+        param.set_val(val);
+    }
+}
+
+// d loss / d p for every p in `params`, as a flat vector in the order of
+// params_to_vec. grads in loss's graph and of every param are cleared first,
+// so this is the gradient of this loss alone (0.0 for params it doesn't use);
+// afterwards the params' grads hold the same values
+pub fn backward_flat(loss: &Node, params: &[Node]) -> Vec<f64> {
+    let graph = loss.topological_order();
+    for node in graph.iter().chain(params.iter()) {
+        node.set_grad(0.0);
+    }
+    loss.set_grad(1.0);
+    loss.backward_pass_ordered(&graph);
+    params.iter().map(|p| p.grad()).collect()
+}
+
+// L2 norm of the current gradients, sqrt(sum of grad^2), e.g. to decide on clipping
+pub fn grad_norm(params: &[Node]) -> f64 {
+    params.iter()
+        .map(|p| p.grad() * p.grad())
+        .sum::<f64>()
+        .sqrt()
+}
+
+
+// ============= GRAD CHECK =============
+// scale-free comparison of two gradient estimates; an absolute epsilon is
+// too strict for large gradients and too loose for tiny ones
+pub fn rel_err(analytic: f64, numeric: f64) -> f64 {
+    (analytic - numeric).abs() / (analytic.abs() + numeric.abs() + 1e-12)
+}
+
+// builds f at `inputs`, backprops, and compares every input grad against a
+// central difference with step h. returns the largest relative error
+pub fn grad_check<F: Fn(&[Node]) -> Node>(f: F, inputs: &[f64], h: f64) -> f64 {
+    let eval = |vals: &[f64]| {
+        let nodes: Vec<Node> = vals.iter().map(|&v| Node::new(v)).collect();
+        f(&nodes).val()
+    };
+
+    let nodes: Vec<Node> = inputs.iter().map(|&v| Node::new(v)).collect();
+    let out = f(&nodes);
+    out.set_grad(1.0);
+    out.backward_pass();
+
+    let mut worst: f64 = 0.0;
+    for (i, node) in nodes.iter().enumerate() {
+        let mut plus = inputs.to_vec();
+        let mut minus = inputs.to_vec();
+        plus[i] += h;
+        minus[i] -= h;
+        let numeric = (eval(&plus) - eval(&minus)) / (2.0 * h);
+        worst = worst.max(rel_err(node.grad(), numeric));
+    }
+    worst
+}
+
+
+// ============= NEURON =============
+// how a neuron's bias is initialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BiasInit {
+    // uniform in [-0.1, 0.1], same as the weights
+    #[default]
+    Random,
+    // exactly 0.0, so only the weights depend on the rng
+    Zero,
+    // no bias at all: the neuron computes act(w.x)
+    None,
+}
+
+impl BiasInit {
+    // the initial bias, or None for bias-free neurons. only Random draws from the rng
+    fn sample(self, rng: &mut impl Rng) -> Option<f64> {
+        match self {
+            BiasInit::Random => Some(rng.gen_range(-0.1..0.1)),
+            BiasInit::Zero => Some(0.0),
+            BiasInit::None => None,
         }
-        outputs
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Neuron {
+    n_in: i64,
+    pub w: Vec<Node>,
+    // None for bias-free neurons
+    pub b: Option<Node>,
+    pub act: Activation,
+    // if set, w.x + b is clamped to [-c, c] before the activation so huge
+    // inputs can't push the sum to inf/NaN; gradients still flow inside the range
+    pub preact_clamp: Option<f64>,
+    pub bias_init: BiasInit,
+}
+
+impl Neuron {
+    pub fn new(n_in: i64, bias: bool) -> Self {
+        let bias_init = if bias { BiasInit::Random } else { BiasInit::None };
+        Neuron::with_init(n_in, Activation::Tanh, bias_init, &mut rand::thread_rng())
+    }
+
+    pub fn with_activation(n_in: i64, act: Activation) -> Self {
+        Neuron::with_rng(n_in, act, &mut rand::thread_rng())
+    }
+
+    // draws the initial weights from `rng`, for reproducible runs
+    pub fn with_rng(n_in: i64, act: Activation, rng: &mut impl Rng) -> Self {
+        Neuron::with_init(n_in, act, BiasInit::Random, rng)
+    }
+
+    // like with_rng, with the bias initialized according to `bias_init`.
+    // with BiasInit::None no bias node is created or drawn from `rng`
+    pub fn with_init(n_in: i64, act: Activation, bias_init: BiasInit, rng: &mut impl Rng) -> Self {
+        // Initialize with smaller weights to prevent saturation
+        let w = (0..n_in)
+            .map(|_| Node::new(rng.gen_range(-0.1..0.1)))
+            .collect();
+            
+        let b = bias_init.sample(rng).map(Node::new);
+        
+        Neuron { n_in, w, b, act, preact_clamp: None, bias_init }
+    }
+
+    // exact weights instead of random ones, for tests and loading models
+    pub fn from_weights(w: Vec<f64>, b: f64) -> Self {
+        Neuron {
+            n_in: w.len() as i64,
+            w: w.into_iter().map(Node::new).collect(),
+            b: Some(Node::new(b)),
+            act: Activation::Tanh,
+            preact_clamp: None,
+            bias_init: BiasInit::default(),
+        }
+    }
+
+    pub fn forward(&self, x: &[Node]) -> Node {
+        self.forward_with(x, &self.act)
+    }
+
+    // forward with `act` in place of the neuron's own activation, for this call
+    // only; handy for ablations. the pre-activation clamp still applies
+    pub fn forward_with(&self, x: &[Node], act: &Activation) -> Node {
+        let z = self.linear(x);
+        match self.preact_clamp {
+            Some(c) => act.apply(&z.clamp(-c, c)),
+            None => act.apply(&z),
+        }
+    }
+
+    // w.x + b before the activation is applied
+    pub fn linear(&self, x: &[Node]) -> Node {
+        assert_eq!(x.len(), self.w.len(), "neuron expects {} inputs", self.n_in);
+        let mut act = match &self.b {
+            Some(b) => b.clone(),
+            None => Node::constant(0.0),
+        };
+        
+        // one fused node per weight instead of a mul and an add
+        for (weight, input) in self.w.iter().zip(x.iter()) {
+            act = weight.mul_add(input, &act);
+        }
+        
+        act
+    }
+
+    // same computation as forward on plain f64s, without building a graph
+    pub fn predict(&self, x: &[f64]) -> f64 {
+        let z = self.w.iter()
+            .zip(x.iter())
+            .fold(self.b.as_ref().map_or(0.0, |b| b.val()), |acc, (w, xi)| acc + w.val() * xi);
+        match self.preact_clamp {
+            Some(c) => self.act.apply_f64(z.clamp(-c, c)),
+            None => self.act.apply_f64(z),
+        }
+    }
+
+    pub fn update_params(&self, learning_rate: f64) {
+        // Add gradient clipping
+        let clip_value = 1.0;
+        
+        for w in &self.w {
+            let grad = w.grad().clamp(-clip_value, clip_value);
+            let mut node = w.0.borrow_mut();
+            node.val -= learning_rate * grad;
+        }
+        
+        if let Some(b) = &self.b {
+            let grad = b.grad().clamp(-clip_value, clip_value);
+            b.set_val(b.val() - learning_rate * grad);
+        }
+    }
+
+    pub fn zero_grad(&self) {
+        for w in &self.w {
+            w.set_grad(0.0);
+        }
+        if let Some(b) = &self.b {
+            b.set_grad(0.0);
+        }
+    }
+
+    // redraws every weight and the bias in place with the same scheme as
+    // with_init, and zeroes their grads. the nodes themselves are kept, so
+    // anything holding them (optimizers, tied layers) sees the new values
+    pub fn reset_parameters(&self, rng: &mut impl Rng) {
+        for w in &self.w {
+            w.set_val(rng.gen_range(-0.1..0.1));
+        }
+        if let Some(b) = &self.b {
+            b.set_val(self.bias_init.sample(rng).unwrap_or(0.0));
+        }
+        self.zero_grad();
+    }
+
+    // handles to the weights followed by the bias, if there is one
+    pub fn parameters(&self) -> Vec<Node> {
+        let mut params = self.w.clone();
+        params.extend(self.b.clone());
+        params
+    }
+
+}
+
+// ============= LAYER =============
+#[derive(Debug, Clone)]
+pub struct Layer{
+    n_in: i64,
+    n_out: i64,
+    neurons: Vec<Neuron>
+}
+impl Layer {
+    pub fn new(n_in: i64, n_out: i64, bias: bool) -> Layer{
+        let bias_init = if bias { BiasInit::Random } else { BiasInit::None };
+        Layer::with_init(n_in, n_out, Activation::Tanh, bias_init, &mut rand::thread_rng())
+    }
+
+    pub fn with_activation(n_in: i64, n_out: i64, act: Activation) -> Layer{
+        Layer::with_rng(n_in, n_out, act, &mut rand::thread_rng())
+    }
+
+    pub fn with_rng(n_in: i64, n_out: i64, act: Activation, rng: &mut impl Rng) -> Layer{
+        Layer::with_init(n_in, n_out, act, BiasInit::Random, rng)
+    }
+
+    pub fn with_init(n_in: i64, n_out: i64, act: Activation, bias_init: BiasInit, rng: &mut impl Rng) -> Layer{
+        let mut neurons: Vec<Neuron> = Vec::new();
+        for _ in 1..=n_out {
+            neurons.push(Neuron::with_init(n_in, act.clone(), bias_init, rng));
+        }
+
+        Layer{
+            n_in,
+            n_out,
+            neurons
+        }
+    }
+
+    // one row of weights and one bias per neuron
+    pub fn from_weights(w: Vec<Vec<f64>>, b: Vec<f64>) -> Layer {
+        assert_eq!(w.len(), b.len(), "need one bias per row of weights");
+        assert!(!w.is_empty(), "layer needs at least one neuron");
+        let n_in = w[0].len() as i64;
+        assert!(w.iter().all(|row| row.len() as i64 == n_in), "weight rows must have the same length");
+
+        let neurons: Vec<Neuron> = w.into_iter()
+            .zip(b)
+            .map(|(w, b)| Neuron::from_weights(w, b))
+            .collect();
+
+        Layer{
+            n_in,
+            n_out: neurons.len() as i64,
+            neurons
+        }
+    }
+
+    pub fn forward(&self, x: Vec<Node>) -> Vec<Node> {
+        assert_eq!(x.len(), self.n_in as usize, "layer expects {} inputs", self.n_in);
+        // every neuron reads the same input slice, no per-neuron copies
+        self.neurons.iter().map(|neuron| neuron.forward(&x)).collect()
+    }    
+
+    // forward into a caller-owned buffer: `out` is cleared and refilled, so a
+    // buffer reused across calls stops allocating once it's big enough
+    pub fn forward_into(&self, x: &[Node], out: &mut Vec<Node>) {
+        assert_eq!(x.len(), self.n_in as usize, "layer expects {} inputs", self.n_in);
+        out.clear();
+        out.extend(self.neurons.iter().map(|neuron| neuron.forward(x)));
     }
 
+    pub fn predict(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.n_in as usize, "layer expects {} inputs", self.n_in);
+        self.neurons.iter().map(|neuron| neuron.predict(x)).collect()
+    }
+    
     pub fn update_params(&mut self, step_size: f64) {
-        for layer in self.layers.iter_mut(){
-            layer.update_params(step_size)
+        for neuron in self.neurons.iter_mut(){
+            neuron.update_params(step_size);
         }
     }
 
     pub fn zero_grad(&mut self) {
-        for layer in self.layers.iter_mut(){
-            layer.zero_grad();
+        for neuron in self.neurons.iter_mut(){
+            neuron.zero_grad();
+        }
+    }
+
+    pub fn reset_parameters(&mut self, rng: &mut impl Rng) {
+        for neuron in self.neurons.iter() {
+            neuron.reset_parameters(rng);
+        }
+    }
+
+    pub fn parameters(&self) -> Vec<Node> {
+        self.neurons.iter().flat_map(|neuron| neuron.parameters()).collect()
+    }
+
+    // makes this layer use other's weight nodes (biases stay separate).
+    // with transpose false the shapes must match and the weights are shared
+    // as-is; with transpose true n_in and n_out must be swapped, as in an
+    // autoencoder decoder, and neuron i reads column i of other's weights.
+    // grads from both layers accumulate into the shared nodes. note that
+    // calling update_params on both layers steps the shared weights twice
+    pub fn tie_weights_to(&mut self, other: &Layer, transpose: bool) {
+        let (n_in, n_out) = if transpose { (other.n_out, other.n_in) } else { (other.n_in, other.n_out) };
+        assert!(
+            self.n_in == n_in && self.n_out == n_out,
+            "can't tie a {}x{} layer to a {}x{} layer (transpose = {})",
+            self.n_in, self.n_out, other.n_in, other.n_out, transpose
+        );
+
+        if transpose {
+            for (i, neuron) in self.neurons.iter_mut().enumerate() {
+                neuron.w = other.neurons.iter().map(|source| source.w[i].clone()).collect();
+            }
+        } else {
+            for (neuron, source) in self.neurons.iter_mut().zip(other.neurons.iter()) {
+                neuron.w = source.w.clone();
+            }
+        }
+    }
+
+    pub fn set_preact_clamp(&mut self, clamp: Option<f64>) {
+        for neuron in self.neurons.iter_mut() {
+            neuron.preact_clamp = clamp;
+        }
+    }
+
+    // weights plus one bias per neuron that has one
+    pub fn num_params(&self) -> usize {
+        self.neurons.iter().map(|neuron| neuron.parameters().len()).sum()
+    }
+
+    // one multiply-add per weight plus one activation per neuron
+    pub fn forward_flops(&self) -> usize {
+        (self.n_in * self.n_out + self.n_out) as usize
+    }
+}
+
+
+// ============= SOFTMAX LAYER =============
+// linear neurons whose outputs are normalized together with softmax,
+// meant as a classification head
+#[derive(Debug, Clone)]
+pub struct SoftmaxLayer {
+    layer: Layer
+}
+impl SoftmaxLayer {
+    pub fn new(n_in: i64, n_out: i64) -> SoftmaxLayer {
+        SoftmaxLayer {
+            layer: Layer::new(n_in, n_out, true)
+        }
+    }
+
+    pub fn forward(&self, x: Vec<Node>) -> Vec<Node> {
+        assert_eq!(x.len(), self.layer.n_in as usize, "layer expects {} inputs", self.layer.n_in);
+        let logits: Vec<Node> = self.layer.neurons.iter()
+            .map(|neuron| neuron.linear(&x))
+            .collect();
+        softmax(&logits)
+    }
+
+    pub fn update_params(&mut self, step_size: f64) {
+        self.layer.update_params(step_size);
+    }
+
+    pub fn zero_grad(&mut self) {
+        self.layer.zero_grad();
+    }
+}
+
+
+// ============= RESIDUAL =============
+// a layer with a skip connection: forward(x) = inner.forward(x) + x, so the
+// inner layer only has to learn a correction to the identity
+#[derive(Debug, Clone)]
+pub struct Residual {
+    pub inner: Layer,
+}
+impl Residual {
+    pub fn new(inner: Layer) -> Residual {
+        assert_eq!(
+            inner.n_in, inner.n_out,
+            "residual needs a layer with as many outputs as inputs, got {} -> {}", inner.n_in, inner.n_out
+        );
+        Residual { inner }
+    }
+
+    pub fn forward(&self, x: Vec<Node>) -> Vec<Node> {
+        let out = self.inner.forward(x.clone());
+        out.elementwise_add(&x)
+    }
+
+    pub fn predict(&self, x: &[f64]) -> Vec<f64> {
+        self.inner.predict(x)
+            .iter()
+            .zip(x.iter())
+            .map(|(o, x)| o + x)
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<Node> {
+        self.inner.parameters()
+    }
+
+    pub fn update_params(&mut self, step_size: f64) {
+        self.inner.update_params(step_size);
+    }
+
+    pub fn zero_grad(&mut self) {
+        self.inner.zero_grad();
+    }
+}
+
+
+// ============= CONV1D =============
+// single-channel 1d convolution with valid padding: output i is the dot
+// product of the kernel with input[i..i + kernel.len()]
+#[derive(Debug, Clone)]
+pub struct Conv1d {
+    pub kernel: Vec<Node>,
+}
+impl Conv1d {
+    pub fn new(kernel_size: usize) -> Conv1d {
+        let mut rng = rand::thread_rng();
+        Conv1d {
+            kernel: (0..kernel_size)
+                .map(|_| Node::new(rng.gen_range(-0.1..0.1)))
+                .collect()
+        }
+    }
+
+    pub fn from_kernel(kernel: Vec<f64>) -> Conv1d {
+        Conv1d {
+            kernel: kernel.into_iter().map(Node::new).collect()
+        }
+    }
+
+    pub fn forward(&self, input: &[Node]) -> Vec<Node> {
+        assert!(
+            input.len() >= self.kernel.len(),
+            "input of length {} is shorter than the kernel ({})", input.len(), self.kernel.len()
+        );
+        input.windows(self.kernel.len())
+            .map(|window| dot(&self.kernel, window))
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<Node> {
+        self.kernel.clone()
+    }
+
+    pub fn update_params(&self, step_size: f64) {
+        for k in self.kernel.iter() {
+            k.set_val(k.val() - step_size * k.grad());
+        }
+    }
+
+    pub fn zero_grad(&self) {
+        for k in self.kernel.iter() {
+            k.set_grad(0.0);
+        }
+    }
+}
+
+
+// ============= MLP =============
+// activation summary of a single layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActStats {
+    pub mean: f64,
+    // fraction of activations with |val| > 0.99, where tanh is flat
+    pub saturated: f64,
+}
+
+impl ActStats {
+    fn from_activations(acts: &[f64]) -> ActStats {
+        let n = acts.len() as f64;
+        let saturated = acts.iter().filter(|a| a.abs() > 0.99).count();
+        ActStats {
+            mean: acts.iter().sum::<f64>() / n,
+            saturated: saturated as f64 / n,
+        }
+    }
+}
+
+// inverted dropout in place: zeroes each node with probability p and scales
+// the rest by 1 / (1 - p)
+fn dropout(acts: &mut [Node], p: f64, rng: &mut impl Rng) {
+    assert!((0.0..1.0).contains(&p), "dropout rate must be in [0, 1), got {}", p);
+    if p == 0.0 {
+        return;
+    }
+    let scale = Node::constant(1.0 / (1.0 - p));
+    for act in acts.iter_mut() {
+        *act = if rng.gen::<f64>() < p {
+            Node::constant(0.0)
+        } else {
+            act.clone() * scale.clone()
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MLP{
+    n_in: i64,
+    n_outs: Vec<i64>,
+    layers: Vec<Layer>,
+    // dropout rate for each hidden layer's outputs, used by forward_train.
+    // may be shorter than the number of hidden layers; empty means no dropout
+    pub dropout: Vec<f64>,
+    // forward_train only drops units while this is set; predict and forward never do
+    pub training: bool,
+}
+
+impl MLP {
+    fn from_layers(layers: Vec<Layer>) -> MLP {
+        MLP {
+            n_in: layers[0].n_in,
+            n_outs: layers.iter().map(|layer| layer.n_out).collect(),
+            layers,
+            dropout: Vec::new(),
+            training: true,
+        }
+    }
+
+    pub fn new(n_in: i64, n_outs: Vec<i64>) -> MLP{
+        MLP::with_rng(n_in, n_outs, &mut rand::thread_rng())
+    }
+
+    // same as new, with all weights drawn from `rng`
+    pub fn with_rng(n_in: i64, n_outs: Vec<i64>, rng: &mut impl Rng) -> MLP{
+        MLP::with_init(n_in, n_outs, BiasInit::Random, rng)
+    }
+
+    // same as with_rng, with every bias initialized according to `bias_init`
+    pub fn with_init(n_in: i64, n_outs: Vec<i64>, bias_init: BiasInit, rng: &mut impl Rng) -> MLP{
+        let mut layers: Vec<Layer> = vec![Layer::with_init(n_in, n_outs[0], Activation::Tanh, bias_init, rng)];
+        for i in 1..n_outs.len() {
+            layers.push(Layer::with_init(n_outs[i-1], n_outs[i], Activation::Tanh, bias_init, rng));
+        }
+
+        MLP::from_layers(layers)
+    }
+
+    // tanh hidden layers followed by a linear layer of n_classes logits,
+    // meant to be fed through softmax / cross-entropy
+    pub fn classifier(n_in: i64, hidden: Vec<i64>, n_classes: i64) -> MLP {
+        let mut layers: Vec<Layer> = Vec::new();
+        let mut prev = n_in;
+        for &n_out in hidden.iter() {
+            layers.push(Layer::new(prev, n_out, true));
+            prev = n_out;
+        }
+        layers.push(Layer::with_activation(prev, n_classes, Activation::Linear));
+
+        MLP::from_layers(layers)
+    }
+
+    // (weights, biases) per layer, in the format of Layer::from_weights
+    pub fn from_weights(layers: Vec<(Vec<Vec<f64>>, Vec<f64>)>) -> MLP {
+        let layers: Vec<Layer> = layers.into_iter()
+            .map(|(w, b)| Layer::from_weights(w, b))
+            .collect();
+        for pair in layers.windows(2) {
+            assert_eq!(pair[0].n_out, pair[1].n_in, "layer sizes don't line up");
         }
+
+        MLP::from_layers(layers)
+    }
+
+    pub fn n_in(&self) -> i64 {
+        self.n_in
+    }
+
+    // output size of each layer, as passed to new
+    pub fn n_outs(&self) -> &[i64] {
+        &self.n_outs
+    }
+
+    pub fn forward(&self, x: Vec<Node>) -> Vec<Node> {
+        // two buffers swapped between layers, so a deep net allocates
+        // one extra Vec per call instead of one per layer
+        let mut inputs: Vec<Node> = x;
+        let mut outputs: Vec<Node> = Vec::new();
+        for layer in self.layers.iter() {
+            layer.forward_into(&inputs, &mut outputs);
+            std::mem::swap(&mut inputs, &mut outputs);
+        }
+        inputs
+    }
+
+    // forward plus the wall-clock time it took to build the graph, for
+    // profiling layer widths
+    pub fn forward_timed(&self, x: Vec<Node>) -> (Vec<Node>, Duration) {
+        let start = Instant::now();
+        let outputs = self.forward(x);
+        (outputs, start.elapsed())
+    }
+
+    // forward with dropout for training: in training mode each unit of hidden
+    // layer i is zeroed with probability dropout[i] and the survivors are scaled
+    // by 1 / (1 - p), so expected activations match forward. with training off
+    // or no rates set this is just forward and draws nothing from `rng`
+    pub fn forward_train(&self, x: Vec<Node>, rng: &mut impl Rng) -> Vec<Node> {
+        if !self.training || self.dropout.is_empty() {
+            return self.forward(x);
+        }
+        assert!(
+            self.dropout.len() < self.layers.len(),
+            "{} dropout rates for {} hidden layers", self.dropout.len(), self.layers.len() - 1
+        );
+
+        let mut inputs: Vec<Node> = x;
+        let mut outputs: Vec<Node> = Vec::new();
+        for (i, layer) in self.layers.iter().enumerate() {
+            layer.forward_into(&inputs, &mut outputs);
+            if let Some(&p) = self.dropout.get(i) {
+                dropout(&mut outputs, p, rng);
+            }
+            std::mem::swap(&mut inputs, &mut outputs);
+        }
+        inputs
+    }
+
+    // inference-only forward pass, no nodes are allocated
+    pub fn predict(&self, x: &[f64]) -> Vec<f64> {
+        let mut outputs = x.to_vec();
+        for layer in self.layers.iter() {
+            outputs = layer.predict(&outputs);
+        }
+        outputs
+    }
+
+    // d output_i / d input_j at `input`: one forward pass, then one backward
+    // pass per output seeded with a one-hot gradient. grads in the graph are
+    // cleared before each pass and after the last, so parameter grads are
+    // left at zero
+    pub fn jacobian(&self, input: &[f64]) -> Vec<Vec<f64>> {
+        let x: Vec<Node> = input.iter().map(|&v| Node::new(v)).collect();
+        let outputs = self.forward(x.clone());
+        let clear = |graph: &[Node]| {
+            for node in graph.iter() {
+                node.set_grad(0.0);
+            }
+        };
+
+        outputs.iter()
+            .map(|output| {
+                let graph = output.topological_order();
+                clear(&graph);
+                output.set_grad(1.0);
+                output.backward_pass_ordered(&graph);
+                let row = x.iter().map(|xi| xi.grad()).collect();
+                clear(&graph);
+                row
+            })
+            .collect()
+    }
+
+    // rough per-input importance: |d loss / d x_i| averaged over `data`, with
+    // the squared error summed over the outputs as the loss. parameter grads
+    // are left at zero
+    pub fn feature_importance(&self, data: &[(Vec<f64>, Vec<f64>)]) -> Vec<f64> {
+        let mut importance = vec![0.0; self.layers[0].n_in as usize];
+        for (inputs, targets) in data.iter() {
+            let x: Vec<Node> = inputs.iter().map(|&v| Node::new(v)).collect();
+            let loss: Node = self.forward(x.clone())
+                .iter()
+                .zip(targets.iter())
+                .map(|(o, &t)| (o.clone() - Node::constant(t)).square())
+                .sum();
+
+            let graph = loss.topological_order();
+            let clear = || {
+                for node in graph.iter() {
+                    node.set_grad(0.0);
+                }
+            };
+            clear();
+            loss.set_grad(1.0);
+            loss.backward_pass_ordered(&graph);
+            for (total, xi) in importance.iter_mut().zip(x.iter()) {
+                *total += xi.grad().abs();
+            }
+            clear();
+        }
+
+        let n = data.len() as f64;
+        importance.iter().map(|total| total / n).collect()
+    }
+
+    // index of the largest output
+    pub fn predict_class(&self, x: &[f64]) -> usize {
+        self.predict(x)
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .expect("model has no outputs")
+    }
+
+    // per-layer activation summary for one input, to check for tanh saturation
+    pub fn activation_stats(&self, x: &[f64]) -> Vec<ActStats> {
+        let mut outputs = x.to_vec();
+        let mut stats = Vec::new();
+        for layer in self.layers.iter() {
+            outputs = layer.predict(&outputs);
+            stats.push(ActStats::from_activations(&outputs));
+        }
+        stats
+    }
+
+    // predict over a batch of independent inputs, spread across threads.
+    // the model itself can't be shared between threads, not even for reads:
+    // Node is an Rc<RefCell<_>> whose refcount and borrow flag aren't atomic.
+    // so the parameter values are copied out once on the calling thread, and
+    // the workers only ever see that plain f64 snapshot, which is Sync
+    #[cfg(feature = "rayon")]
+    pub fn predict_batch_par(&self, inputs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        use rayon::prelude::*;
+
+        let snapshot: Vec<Vec<FrozenNeuron>> = self.layers.iter()
+            .map(|layer| layer.neurons.iter().map(FrozenNeuron::from_neuron).collect())
+            .collect();
+
+        inputs.par_iter()
+            .map(|x| {
+                let mut outputs = x.clone();
+                for layer in snapshot.iter() {
+                    outputs = layer.iter().map(|neuron| neuron.predict(&outputs)).collect();
+                }
+                outputs
+            })
+            .collect()
+    }
+
+    pub fn update_params(&mut self, step_size: f64) {
+        for layer in self.layers.iter_mut(){
+            layer.update_params(step_size)
+        }
+    }
+
+    pub fn zero_grad(&mut self) {
+        for layer in self.layers.iter_mut(){
+            layer.zero_grad();
+        }
+    }
+
+    // reinitializes every layer in place, keeping the architecture
+    pub fn reset_parameters(&mut self, rng: &mut impl Rng) {
+        for layer in self.layers.iter_mut() {
+            layer.reset_parameters(rng);
+        }
+    }
+
+    pub fn parameters(&self) -> Vec<Node> {
+        self.layers.iter().flat_map(|layer| layer.parameters()).collect()
+    }
+
+    // parameters grouped by layer, input layer first
+    pub fn layer_parameters(&self) -> Vec<Vec<Node>> {
+        self.layers.iter().map(|layer| layer.parameters()).collect()
+    }
+
+    // see Neuron::preact_clamp
+    pub fn set_preact_clamp(&mut self, clamp: Option<f64>) {
+        for layer in self.layers.iter_mut() {
+            layer.set_preact_clamp(clamp);
+        }
+    }
+
+    // L2 norm of the parameter gradients of each layer, input layer first,
+    // for spotting gradients that vanish on their way back
+    pub fn layer_grad_norms(&self) -> Vec<f64> {
+        self.layers.iter()
+            .map(|layer| grad_norm(&layer.parameters()))
+            .collect()
+    }
+
+    // zeroes any NaN or infinite parameter gradient before an update step,
+    // returns how many had to be replaced
+    pub fn sanitize_grads(&self) -> usize {
+        let mut sanitized = 0;
+        for param in self.parameters() {
+            if !param.grad().is_finite() {
+                param.set_grad(0.0);
+                sanitized += 1;
+            }
+        }
+        sanitized
+    }
+
+    pub fn num_params(&self) -> usize {
+        self.layers.iter().map(|layer| layer.num_params()).sum()
+    }
+
+    // rough cost of one forward pass, for comparing architectures
+    pub fn forward_flops(&self) -> usize {
+        self.layers.iter().map(|layer| layer.forward_flops()).sum()
+    }
+
+    // keras-style overview: one row per layer plus the total parameter count
+    pub fn summary(&self) -> String {
+        let mut out = format!("{:<8}{:<10}{:<12}{:>8}{:>10}\n", "Layer", "Type", "Activation", "Output", "Params");
+        for (i, layer) in self.layers.iter().enumerate() {
+            let activation = layer.neurons.first().map_or("-", |neuron| neuron.act.name());
+            out.push_str(&format!(
+                "{:<8}{:<10}{:<12}{:>8}{:>10}\n",
+                i, "Dense", activation, layer.n_out, layer.num_params()
+            ));
+        }
+        out.push_str(&format!("Total params: {}\n", self.num_params()));
+        out
+    }
+}
+
+
+// plain-f64 copy of a neuron, which unlike Neuron can be shared across threads
+#[cfg(feature = "rayon")]
+struct FrozenNeuron {
+    w: Vec<f64>,
+    b: f64,
+    act: Activation,
+    preact_clamp: Option<f64>,
+}
+
+#[cfg(feature = "rayon")]
+impl FrozenNeuron {
+    fn from_neuron(neuron: &Neuron) -> FrozenNeuron {
+        FrozenNeuron {
+            w: neuron.w.iter().map(|w| w.val()).collect(),
+            b: neuron.b.as_ref().map_or(0.0, |b| b.val()),
+            act: neuron.act.clone(),
+            preact_clamp: neuron.preact_clamp,
+        }
+    }
+
+    // mirrors Neuron::predict
+    fn predict(&self, x: &[f64]) -> f64 {
+        let z = self.w.iter().zip(x.iter()).fold(self.b, |acc, (w, xi)| acc + w * xi);
+        match self.preact_clamp {
+            Some(c) => self.act.apply_f64(z.clamp(-c, c)),
+            None => self.act.apply_f64(z),
+        }
+    }
+}
+
+
+// ============= DECISION GRID =============
+// samples the first output of a 2-input model on a resolution x resolution grid.
+// grid[i][j] is the output at y = y_range.0 + i * dy, x = x_range.0 + j * dx,
+// so rows go from the bottom of the plot to the top
+pub fn decision_grid(mlp: &MLP, x_range: (f64, f64), y_range: (f64, f64), resolution: usize) -> Vec<Vec<f64>> {
+    assert!(resolution >= 2, "decision_grid needs a resolution of at least 2");
+    let step = |(lo, hi): (f64, f64), i: usize| lo + (hi - lo) * i as f64 / (resolution - 1) as f64;
+
+    (0..resolution)
+        .map(|i| {
+            let y = step(y_range, i);
+            (0..resolution)
+                .map(|j| mlp.predict(&[step(x_range, j), y])[0])
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn forward_flops_and_num_params_by_hand() {
+        let mlp = MLP::new(2, vec![16, 8, 1]);
+        // (2*16 + 16) + (16*8 + 8) + (8*1 + 1)
+        assert_eq!(mlp.forward_flops(), 193);
+        assert_eq!(mlp.num_params(), 193);
+        assert_eq!(Layer::new(3, 4, true).forward_flops(), 16);
+    }
+
+    #[test]
+    fn top_grad_inputs_ranks_the_largest_weight_first() {
+        let neuron = Neuron::from_weights(vec![0.1, -2.0, 0.5], 0.0);
+        let x: Vec<Node> = [0.2, 0.3, -0.1].iter().map(|&v| Node::new(v)).collect();
+        let out = neuron.forward(&x);
+        out.set_grad(1.0);
+        out.backward_pass();
+
+        let top = top_grad_inputs(&x, 2);
+        assert_eq!(top.iter().map(|&(i, _)| i).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(top[0].1 < 0.0);
+    }
+
+    #[test]
+    fn softmax_layer_outputs_a_distribution_with_prob_minus_onehot_grads() {
+        let head = SoftmaxLayer::new(3, 4);
+        let x: Vec<Node> = [0.5, -1.0, 2.0].iter().map(|&v| Node::new(v)).collect();
+        let probs = head.forward(x);
+        assert!((probs.iter().map(|p| p.val()).sum::<f64>() - 1.0).abs() < 1e-12);
+
+        // cross-entropy -ln(p[2]) has d/dp[2] = -1 / p[2]; seeding that gives
+        // the cross-entropy grads, and each bias moves its logit one for one
+        let target = 2;
+        probs[target].set_grad(-1.0 / probs[target].val());
+        probs[target].backward_pass();
+        for (i, (neuron, p)) in head.layer.neurons.iter().zip(probs.iter()).enumerate() {
+            let onehot = if i == target { 1.0 } else { 0.0 };
+            assert!((neuron.b.as_ref().unwrap().grad() - (p.val() - onehot)).abs() < 1e-12, "class {}", i);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn predict_batch_par_matches_predict() {
+        let mlp = MLP::new(2, vec![8, 3]);
+        let inputs: Vec<Vec<f64>> = (0..50).map(|i| vec![i as f64 * 0.1 - 2.5, (i as f64).sin()]).collect();
+
+        let serial: Vec<Vec<f64>> = inputs.iter().map(|x| mlp.predict(x)).collect();
+        assert_eq!(mlp.predict_batch_par(&inputs), serial);
+    }
+
+    #[test]
+    fn scale_grad_flips_the_grad_and_keeps_the_value() {
+        let x = Node::new(1.5);
+        let plain = x.square();
+        plain.set_grad(1.0);
+        plain.backward_pass();
+        let expected = x.grad();
+
+        let y = Node::new(1.5);
+        let reversed = y.square().scale_grad(-1.0);
+        assert_eq!(reversed.val(), plain.val());
+        reversed.set_grad(1.0);
+        reversed.backward_pass();
+        assert_eq!(y.grad(), -expected);
+    }
+
+    #[test]
+    fn activation_stats_separates_saturated_layers() {
+        let mlp = MLP::from_weights(vec![(vec![vec![50.0], vec![-50.0], vec![0.01]], vec![0.0; 3])]);
+        let high = mlp.activation_stats(&[1.0]);
+        let low = mlp.activation_stats(&[0.001]);
+        assert_eq!(high.len(), 1);
+        assert!((high[0].saturated - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(low[0].saturated, 0.0);
+        assert!(low[0].mean.abs() < 0.1);
+    }
+
+    #[test]
+    fn from_weights_neuron_computes_tanh_exactly() {
+        let neuron = Neuron::from_weights(vec![0.5, -1.0], 0.25);
+        let x = vec![Node::new(2.0), Node::new(0.5)];
+        let expected = (0.5f64 * 2.0 - 1.0 * 0.5 + 0.25).tanh();
+        assert_eq!(neuron.forward(&x).val(), expected);
+        assert_eq!(neuron.predict(&[2.0, 0.5]), expected);
+
+        let mlp = MLP::from_weights(vec![(vec![vec![0.5, -1.0]], vec![0.25])]);
+        assert_eq!((mlp.n_in(), mlp.n_outs()), (2, &[1][..]));
+        assert_eq!(mlp.predict(&[2.0, 0.5]), vec![expected]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "seed the root")]
+    fn backward_pass_without_a_seed_panics() {
+        let x = Node::new(0.5);
+        x.tanh().backward_pass();
+    }
+
+    #[test]
+    fn sanitize_grads_zeroes_and_counts_non_finite_grads() {
+        let mlp = MLP::from_weights(vec![(vec![vec![0.1, 0.2]], vec![0.3])]);
+        let params = mlp.parameters();
+        params[0].set_grad(f64::NAN);
+        params[1].set_grad(0.5);
+        params[2].set_grad(f64::INFINITY);
+
+        assert_eq!(mlp.sanitize_grads(), 2);
+        assert_eq!(params.iter().map(|p| p.grad()).collect::<Vec<_>>(), vec![0.0, 0.5, 0.0]);
+        assert_eq!(mlp.sanitize_grads(), 0);
+    }
+
+    #[test]
+    fn log_softmax_matches_ln_softmax_and_survives_large_logits() {
+        let close = |a: f64, b: f64, tol: f64| assert!((a - b).abs() < tol, "{} vs {}", a, b);
+        let logits: Vec<Node> = [1.0, -0.5, 3.0].iter().map(|&v| Node::new(v)).collect();
+        for (log_p, p) in log_softmax(&logits).iter().zip(softmax(&logits).iter()) {
+            close(log_p.val(), p.val().ln(), 1e-12);
+        }
+
+        // softmax can't represent these: exp's clamp floors the smaller
+        // probabilities at e^-50, so their ln is far off
+        let large: Vec<Node> = [1000.0, 0.0, -1000.0].iter().map(|&v| Node::new(v)).collect();
+        assert!(softmax(&large)[1].val().ln() > -51.0);
+        let log_p: Vec<f64> = log_softmax(&large).iter().map(|l| l.val()).collect();
+        close(log_p[0], 0.0, 1e-12);
+        close(log_p[1], -1000.0, 1e-9);
+        close(log_p[2], -2000.0, 1e-9);
+    }
+
+    #[test]
+    fn sum_gives_unit_grads_and_folds() {
+        let xs = vec![Node::new(1.5), Node::new(-2.0), Node::new(4.0)];
+        let total: Node = xs.iter().sum();
+        assert_eq!(total.val(), 3.5);
+
+        total.set_grad(1.0);
+        total.backward_pass();
+        for x in xs.iter() {
+            assert_eq!(x.grad(), 1.0);
+        }
+
+        let owned: Node = xs.into_iter().sum();
+        assert_eq!(owned.val(), 3.5);
+        let constants: Node = [Node::constant(1.0), Node::constant(2.0)].iter().sum();
+        assert_eq!(constants.fold_constants().graph_size(), 1);
+    }
+
+    #[test]
+    fn names_round_trip_and_default_to_the_op() {
+        let w = Node::new(0.5).with_name("w");
+        assert_eq!(w.name(), "w");
+        assert_eq!(Node::new(1.0).name(), "value");
+        assert_eq!((w.clone() * Node::new(2.0)).name(), "mul");
+        assert_eq!(w.tanh().name(), "tanh");
+    }
+
+    #[test]
+    fn decision_grid_has_the_right_shape_and_corners() {
+        let mlp = MLP::new(2, vec![4, 1]);
+        let grid = decision_grid(&mlp, (-1.0, 2.0), (-3.0, 0.5), 5);
+        assert_eq!(grid.len(), 5);
+        assert!(grid.iter().all(|row| row.len() == 5));
+
+        assert_eq!(grid[0][0], mlp.predict(&[-1.0, -3.0])[0]);
+        assert_eq!(grid[0][4], mlp.predict(&[2.0, -3.0])[0]);
+        assert_eq!(grid[4][0], mlp.predict(&[-1.0, 0.5])[0]);
+        assert_eq!(grid[4][4], mlp.predict(&[2.0, 0.5])[0]);
+    }
+
+    #[test]
+    fn next_generation_zeroes_every_grad() {
+        let x = Node::new(0.5);
+        let w = Node::new(-1.5);
+        let out = (x.clone() * w.clone()).tanh();
+        out.set_grad(1.0);
+        out.backward_pass();
+        assert!(x.grad() != 0.0 && w.grad() != 0.0);
+
+        next_generation();
+        for node in out.topological_order() {
+            assert_eq!(node.grad(), 0.0);
+        }
+        // and grads written afterwards count again
+        x.set_grad(2.0);
+        assert_eq!(x.grad(), 2.0);
+    }
+
+    #[test]
+    fn next_generation_also_zeroes_other_models_on_the_thread() {
+        let a = MLP::from_weights(vec![(vec![vec![0.5]], vec![0.0])]);
+        let b = MLP::from_weights(vec![(vec![vec![-0.5]], vec![0.0])]);
+        for p in a.parameters().iter().chain(b.parameters().iter()) {
+            p.set_grad(1.0);
+        }
+
+        next_generation();
+        assert!(b.parameters().iter().all(|p| p.grad() == 0.0));
+    }
+
+    #[test]
+    fn predict_class_is_the_argmax() {
+        let mlp = MLP::from_weights(vec![(vec![vec![1.0], vec![-1.0], vec![0.5]], vec![0.0; 3])]);
+        assert_eq!(mlp.predict_class(&[1.0]), 0);
+        assert_eq!(mlp.predict_class(&[-1.0]), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips_a_value_node() {
+        let node = Node::new(1.25).with_name("w");
+        let json = serde_json::to_string(&node).unwrap();
+        assert_eq!(json, r#"{"val":1.25,"name":"w"}"#);
+
+        let back: Node = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.val(), 1.25);
+        assert_eq!(back.name(), "w");
+        assert!(!back.ptr_eq(&node));
+
+        let unnamed: Node = serde_json::from_str(&serde_json::to_string(&Node::new(-3.0)).unwrap()).unwrap();
+        assert_eq!((unnamed.val(), unnamed.name()), (-3.0, "value".to_string()));
+    }
+
+    #[test]
+    fn pow_partials_match_finite_differences() {
+        let err = grad_check(|xs| xs[0].pow(&xs[1]), &[1.7, 2.3], 1e-6);
+        assert!(err < 1e-6, "relative error {}", err);
+        let err = grad_check(|xs| xs[0].pow(&xs[1]), &[0.4, -1.5], 1e-6);
+        assert!(err < 1e-6, "relative error {}", err);
+    }
+
+    #[test]
+    fn rel_err_is_scale_free() {
+        assert!(rel_err(1000.0, 1000.001) < 1e-6);
+        assert!(rel_err(1e-6, 2e-6) > 0.3);
+        assert_eq!(rel_err(0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn layer_grad_norms_match_a_manual_computation() {
+        let mlp = MLP::from_weights(vec![
+            (vec![vec![0.1, 0.2], vec![0.3, 0.4]], vec![0.0, 0.0]),
+            (vec![vec![0.5, 0.6]], vec![0.0]),
+        ]);
+        let grads = [vec![1.0, 2.0, 0.0, 2.0, 0.0, 0.0], vec![3.0, 0.0, 4.0]];
+        for (layer, grads) in mlp.layers.iter().zip(grads.iter()) {
+            for (p, &g) in layer.parameters().iter().zip(grads.iter()) {
+                p.set_grad(g);
+            }
+        }
+        assert_eq!(mlp.layer_grad_norms(), vec![3.0, 5.0]);
+    }
+
+    #[test]
+    fn sub_is_one_node_with_plus_minus_one_grads() {
+        let a = Node::new(5.0);
+        let b = Node::new(3.0);
+        let d = a.clone() - b.clone();
+        assert_eq!(d.val(), 2.0);
+        assert_eq!(d.graph_size(), 3);
+        assert_eq!(d.name(), "sub");
+
+        d.set_grad(1.0);
+        d.backward_pass();
+        assert_eq!((a.grad(), b.grad()), (1.0, -1.0));
+    }
+
+    #[test]
+    fn value_and_grad_matches_the_accessors() {
+        let x = Node::new(0.3);
+        let y = x.tanh();
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_eq!(x.value_and_grad(), (x.val(), x.grad()));
+        assert_eq!(y.value_and_grad(), (y.val(), 1.0));
+    }
+
+    #[test]
+    fn default_is_a_zero_leaf_that_seeds_a_sum() {
+        let zero = Node::default();
+        assert_eq!(zero.val(), 0.0);
+        assert!(zero.children().is_empty());
+        assert!(!zero.is_constant());
+
+        let xs = [Node::new(1.0), Node::new(2.5)];
+        let total = xs.iter().fold(Node::default(), |acc, x| acc + x.clone());
+        assert_eq!(total.val(), 3.5);
+        total.set_grad(1.0);
+        total.backward_pass();
+        assert_eq!(xs[0].grad(), 1.0);
+    }
+
+    #[test]
+    fn children_are_the_same_nodes() {
+        let a = Node::new(1.0);
+        let b = Node::new(2.0);
+        let children = (a.clone() + b.clone()).children();
+        assert_eq!(children.len(), 2);
+        assert!(children[0].ptr_eq(&a));
+        assert!(children[1].ptr_eq(&b));
+        assert!(!children[0].ptr_eq(&Node::new(1.0)));
+        assert!(a.children().is_empty());
+    }
+
+    #[test]
+    fn conv1d_with_a_known_kernel() {
+        let conv = Conv1d::from_kernel(vec![1.0, 0.0, -1.0]);
+        let input: Vec<Node> = [1.0, 2.0, 4.0, 7.0].iter().map(|&v| Node::new(v)).collect();
+        let out = conv.forward(&input);
+        assert_eq!(out.iter().map(|o| o.val()).collect::<Vec<_>>(), vec![-3.0, -5.0]);
+
+        // d (sum of outputs) / d kernel[k] = sum of the inputs under tap k
+        let total: Node = out.iter().sum();
+        total.set_grad(1.0);
+        total.backward_pass();
+        let grads: Vec<f64> = conv.kernel.iter().map(|k| k.grad()).collect();
+        assert_eq!(grads, vec![1.0 + 2.0, 2.0 + 4.0, 4.0 + 7.0]);
+    }
+
+    #[test]
+    fn hardtanh_passes_grads_only_inside() {
+        for (x, val, grad) in [(0.5, 0.5, 1.0), (3.0, 1.0, 0.0), (-2.0, -1.0, 0.0)] {
+            let node = Node::new(x);
+            let y = node.hardtanh();
+            assert_eq!(y.val(), val);
+            y.set_grad(1.0);
+            y.backward_pass();
+            assert_eq!(node.grad(), grad);
+        }
+    }
+
+    #[test]
+    fn scale_vector_grads() {
+        let v: Vec<Node> = [1.0, -2.0, 4.0].iter().map(|&x| Node::new(x)).collect();
+        let s = Node::new(3.0);
+        let scaled = scale_vector(&v, &s);
+        assert_eq!(scaled.iter().map(|x| x.val()).collect::<Vec<_>>(), vec![3.0, -6.0, 12.0]);
+
+        let total: Node = scaled.iter().sum();
+        total.set_grad(1.0);
+        total.backward_pass();
+        assert!(v.iter().all(|x| x.grad() == 3.0));
+        assert_eq!(s.grad(), 1.0 - 2.0 + 4.0);
+    }
+
+    #[test]
+    fn preact_clamp_keeps_huge_inputs_finite() {
+        let mut neuron = Neuron::from_weights(vec![1e300, 1e300], 0.0);
+        let x = [Node::new(1e10), Node::new(1e10)];
+        assert!(neuron.linear(&x).val().is_infinite());
+
+        neuron.preact_clamp = Some(20.0);
+        let y = neuron.forward(&x);
+        assert_eq!(y.val(), 20f64.tanh());
+        y.set_grad(1.0);
+        y.backward_pass();
+        for p in neuron.parameters().iter().chain(x.iter()) {
+            assert!(p.grad().is_finite());
+        }
+    }
+
+    #[test]
+    fn accumulate_grad_adds_up() {
+        let x = Node::new(1.0);
+        x.accumulate_grad(0.5);
+        x.accumulate_grad(0.25);
+        assert_eq!(x.grad(), 0.75);
+
+        let c = Node::constant(1.0);
+        c.accumulate_grad(0.5);
+        assert_eq!(c.grad(), 0.0);
+    }
+
+    #[test]
+    fn tied_layers_share_weight_updates() {
+        let encoder = Layer::from_weights(vec![vec![0.1, 0.2], vec![0.3, 0.4], vec![0.5, 0.6]], vec![0.0; 3]);
+        let mut decoder = Layer::from_weights(vec![vec![0.0; 3], vec![0.0; 3]], vec![0.0; 2]);
+        decoder.tie_weights_to(&encoder, true);
+        // transposed: decoder neuron i reads column i of the encoder
+        assert!(decoder.neurons[1].w[2].ptr_eq(&encoder.neurons[2].w[1]));
+
+        let x = vec![Node::new(1.0), Node::new(-1.0)];
+        let out: Node = decoder.forward(encoder.forward(x)).iter().sum();
+        out.set_grad(1.0);
+        out.backward_pass();
+
+        let shared = encoder.neurons[0].w[0].clone();
+        let before = shared.val();
+        let grad = shared.grad();
+        assert!(grad != 0.0);
+        decoder.update_params(0.1);
+        assert_close(encoder.neurons[0].w[0].val(), before - 0.1 * grad, 1e-12);
+    }
+
+    #[test]
+    fn tie_weights_to_takes_the_orientation_explicitly() {
+        // square layers fit both ways, so the orientation can't come from the shape
+        let source = Layer::from_weights(vec![vec![1.0, 2.0], vec![3.0, 4.0]], vec![0.0; 2]);
+        let mut same = Layer::from_weights(vec![vec![0.0; 2]; 2], vec![0.0; 2]);
+        let mut transposed = same.clone();
+        same.tie_weights_to(&source, false);
+        transposed.tie_weights_to(&source, true);
+        assert!(same.neurons[0].w[1].ptr_eq(&source.neurons[0].w[1]));
+        assert!(transposed.neurons[0].w[1].ptr_eq(&source.neurons[1].w[0]));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't tie a 2x3 layer to a 2x3 layer (transpose = true)")]
+    fn tie_weights_to_rejects_a_mismatched_orientation() {
+        let source = Layer::from_weights(vec![vec![0.0; 2]; 3], vec![0.0; 3]);
+        let mut other = source.clone();
+        other.tie_weights_to(&source, true);
+    }
+
+    #[test]
+    fn sign_has_no_gradient() {
+        assert_eq!(Node::new(-2.5).sign().val(), -1.0);
+        assert_eq!(Node::new(0.0).sign().val(), 0.0);
+
+        let x = Node::new(3.0);
+        let y = x.sign() * x.clone();
+        assert_eq!(y.val(), 3.0);
+        y.set_grad(1.0);
+        y.backward_pass();
+        // only the direct use of x contributes
+        assert_eq!(x.grad(), 1.0);
+    }
+
+    #[test]
+    fn snapshots_capture_a_point_in_time() {
+        let x = Node::new(2.0);
+        let y = x.square();
+        let before = y.snapshot_graph();
+        assert_eq!(before, vec![(2.0, 0.0, "value"), (4.0, 0.0, "mul")]);
+
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_eq!(y.snapshot_graph(), vec![(2.0, 4.0, "value"), (4.0, 1.0, "mul")]);
+        assert_eq!(x.snapshot(), (2.0, 4.0, "value"));
+        assert_eq!(before[0].1, 0.0);
+    }
+
+    #[test]
+    fn tanh_backward_reads_the_current_input() {
+        let x = Node::new(0.5);
+        let y = x.tanh();
+        x.set_val(2.0);
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_close(x.grad(), 1.0 - 2f64.tanh().powi(2), 1e-12);
+    }
+
+    // values, and grads of the sum of the outputs w.r.t. `inputs`
+    fn vals_and_grads(outputs: &[Node], inputs: &[Node]) -> (Vec<f64>, Vec<f64>) {
+        let total: Node = outputs.iter().sum();
+        total.set_grad(1.0);
+        total.backward_pass();
+        let vals = outputs.iter().map(|o| o.val()).collect();
+        let grads = inputs.iter().map(|i| i.grad()).collect();
+        next_generation();
+        (vals, grads)
+    }
+
+    #[test]
+    fn node_vec_ops_values_and_grads() {
+        let a: Vec<Node> = [1.0, -2.0].iter().map(|&v| Node::new(v)).collect();
+        let b: Vec<Node> = [3.0, 0.5].iter().map(|&v| Node::new(v)).collect();
+
+        assert_eq!(vals_and_grads(&a.add_scalar(1.5), &a), (vec![2.5, -0.5], vec![1.0, 1.0]));
+        assert_eq!(vals_and_grads(&a.mul_scalar(3.0), &a), (vec![3.0, -6.0], vec![3.0, 3.0]));
+        assert_eq!(vals_and_grads(&a.elementwise_add(&b), &b), (vec![4.0, -1.5], vec![1.0, 1.0]));
+        assert_eq!(vals_and_grads(&a.elementwise_mul(&b), &a), (vec![3.0, -1.0], vec![3.0, 0.5]));
+
+        let v: Vec<Node> = [3.0, 4.0].iter().map(|&x| Node::new(x)).collect();
+        let (norm, grads) = vals_and_grads(&[v.l2_norm()], &v);
+        assert_eq!(norm, vec![5.0]);
+        assert_close(grads[0], 0.6, 1e-12);
+        assert_close(grads[1], 0.8, 1e-12);
+    }
+
+    #[test]
+    fn constants_never_receive_grads() {
+        let x = Node::new(2.0);
+        let c = Node::constant(3.0);
+        let y = x.clone() * c.clone() + c.clone();
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_eq!(x.grad(), 3.0);
+        assert_eq!(c.grad(), 0.0);
+        assert!(c.is_constant() && !x.is_constant());
+    }
+
+    #[test]
+    fn graph_size_counts_distinct_nodes() {
+        let (a, b, c) = (Node::new(1.0), Node::new(2.0), Node::new(3.0));
+        assert_eq!((a.clone() * b + c).graph_size(), 5);
+        // a shared node counts once
+        assert_eq!((a.clone() * a).graph_size(), 2);
+    }
+
+    #[test]
+    fn bias_free_neurons_have_only_weights() {
+        assert_eq!(Neuron::new(3, false).parameters().len(), 3);
+        assert_eq!(Neuron::new(3, true).parameters().len(), 4);
+        assert_eq!(Layer::new(3, 2, false).num_params(), 6);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let neuron = Neuron::with_init(3, Activation::Tanh, BiasInit::None, &mut rng);
+        assert!(neuron.b.is_none());
+        // only the three weights were drawn
+        let mut expected = StdRng::seed_from_u64(7);
+        for _ in 0..3 {
+            expected.gen_range(-0.1..0.1);
+        }
+        assert_eq!(rng.gen::<u64>(), expected.gen::<u64>());
+
+        let mlp = MLP::with_init(2, vec![4, 1], BiasInit::None, &mut rng);
+        assert_eq!(mlp.num_params(), 2 * 4 + 4);
+    }
+
+    #[test]
+    fn mul_add_matches_mul_then_add() {
+        let (x, a, b) = (Node::new(1.5), Node::new(-2.0), Node::new(0.25));
+        let fused = x.mul_add(&a, &b).tanh();
+        fused.set_grad(1.0);
+        fused.backward_pass();
+        let fused_grads = [x.grad(), a.grad(), b.grad()];
+
+        let (x2, a2, b2) = (Node::new(1.5), Node::new(-2.0), Node::new(0.25));
+        let naive = (x2.clone() * a2.clone() + b2.clone()).tanh();
+        naive.set_grad(1.0);
+        naive.backward_pass();
+
+        assert_eq!(fused.val(), naive.val());
+        assert_eq!(fused_grads, [x2.grad(), a2.grad(), b2.grad()]);
+    }
+
+    #[test]
+    fn summary_lists_each_layer_with_its_params() {
+        let summary = MLP::new(2, vec![16, 8, 1]).summary();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("Layer"));
+        for (line, (size, params)) in lines[1..4].iter().zip([(16, 48), (8, 136), (1, 9)]) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(fields[1..], ["Dense", "tanh", &size.to_string(), &params.to_string()]);
+        }
+        assert_eq!(lines[4], "Total params: 193");
+    }
+
+    #[test]
+    fn jacobian_of_a_linear_mlp_is_the_weight_product() {
+        // W1 is 3x2, W2 is 2x3, so the jacobian is W2 * W1, 2x2
+        let mut mlp = MLP::from_weights(vec![
+            (vec![vec![1.0, 2.0], vec![0.5, -1.0], vec![-3.0, 0.25]], vec![0.1, 0.2, 0.3]),
+            (vec![vec![2.0, 0.0, 1.0], vec![-1.0, 4.0, 0.5]], vec![-0.4, 0.6]),
+        ]);
+        for neuron in mlp.layers.iter_mut().flat_map(|layer| layer.neurons.iter_mut()) {
+            neuron.act = Activation::Linear;
+        }
+        let jacobian = mlp.jacobian(&[0.7, -1.3]);
+        assert_eq!(jacobian, vec![vec![-1.0, 4.25], vec![-0.5, -5.875]]);
+        // the same at any input, and parameter grads are left at zero
+        assert_eq!(mlp.jacobian(&[5.0, 2.0]), jacobian);
+        assert!(mlp.parameters().iter().all(|p| p.grad() == 0.0));
+    }
+
+    #[test]
+    fn zero_bias_init_gives_exactly_zero_biases() {
+        let mlp = MLP::with_init(3, vec![4, 2], BiasInit::Zero, &mut StdRng::seed_from_u64(5));
+        let biases: Vec<f64> = mlp.layers.iter()
+            .flat_map(|layer| layer.neurons.iter())
+            .map(|neuron| neuron.b.as_ref().unwrap().val())
+            .collect();
+        assert_eq!(biases, vec![0.0; 6]);
+        // the weights are still random
+        assert!(mlp.layers[0].neurons[0].w.iter().all(|w| w.val() != 0.0));
+    }
+
+    #[test]
+    fn powi_matches_square_times_self_and_recip() {
+        // (value, grad) of f at x = 1.7
+        let eval = |f: &dyn Fn(&Node) -> Node| {
+            let x = Node::new(1.7);
+            let y = f(&x);
+            y.set_grad(1.0);
+            y.backward_pass();
+            (y.val(), x.grad())
+        };
+        let (cube, d_cube) = eval(&|x| x.powi(3));
+        let (naive, d_naive) = eval(&|x| x.square() * x.clone());
+        assert_close(cube, naive, 1e-12);
+        assert_close(d_cube, d_naive, 1e-12);
+
+        let (inv, d_inv) = eval(&|x| x.powi(-1));
+        let (recip, d_recip) = eval(&|x| x.recip());
+        assert_close(inv, recip, 1e-12);
+        assert_close(d_inv, d_recip, 1e-12);
+    }
+
+    #[test]
+    fn cached_order_matches_a_fresh_backward_pass() {
+        // (w * x + b).tanh() * x, with x used twice
+        let build = || {
+            let (w, x, b) = (Node::new(0.8), Node::new(-1.2), Node::new(0.3));
+            let y = w.mul_add(&x, &b).tanh() * x.clone();
+            (y, [w, x, b])
+        };
+
+        let (fresh, fresh_leaves) = build();
+        fresh.set_grad(1.0);
+        fresh.backward_pass();
+        let expected: Vec<f64> = fresh_leaves.iter().map(|n| n.grad()).collect();
+
+        let (cached, leaves) = build();
+        let order = cached.topological_order();
+        // reusing the order across passes gives the same grads every time
+        for _ in 0..3 {
+            next_generation();
+            cached.set_grad(1.0);
+            cached.backward_pass_ordered(&order);
+            let grads: Vec<f64> = leaves.iter().map(|n| n.grad()).collect();
+            assert_eq!(grads, expected);
+        }
+    }
+
+    #[test]
+    fn softplus_grad_is_sigmoid_and_large_inputs_stay_finite() {
+        for x in [-3.0, -0.5, 0.0, 0.7, 4.0] {
+            let node = Node::new(x);
+            let y = node.softplus();
+            y.set_grad(1.0);
+            y.backward_pass();
+            assert_close(y.val(), (1.0 + f64::exp(x)).ln(), 1e-12);
+            assert_close(node.grad(), 1.0 / (1.0 + f64::exp(-x)), 1e-12);
+        }
+
+        // the naive ln(1 + e^x) is inf at 1000
+        let big = Node::new(1000.0);
+        let y = big.softplus();
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_eq!(y.val(), 1000.0);
+        assert_eq!(big.grad(), 1.0);
+
+        let small = Node::new(-1000.0);
+        let y = small.softplus();
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_eq!(y.val(), 0.0);
+        assert_eq!(small.grad(), 0.0);
+    }
+
+    #[test]
+    fn stale_grads_are_counted_until_zeroed() {
+        let x = Node::new(0.5);
+        let hidden = x.tanh();
+        let out = hidden.clone() * hidden;
+        let order = out.topological_order();
+        assert_eq!(out.stale_grad_count(&order), 0);
+
+        out.set_grad(1.0);
+        out.backward_pass_ordered(&order);
+        // tanh still holds its grad from the pass
+        assert_eq!(out.stale_grad_count(&order), 1);
+
+        next_generation();
+        assert_eq!(out.stale_grad_count(&order), 0);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn stale_grads_warn_through_the_hook() {
+        let warnings = Rc::new(RefCell::new(Vec::new()));
+        let sink = warnings.clone();
+        set_warning_hook(move |msg| sink.borrow_mut().push(msg.to_string()));
+
+        let x = Node::new(0.5);
+        let out = x.tanh().square();
+        out.set_grad(1.0);
+        out.backward_pass();
+        assert!(warnings.borrow().is_empty());
+
+        // a second pass without zeroing finds the tanh grad from the first
+        out.set_grad(1.0);
+        out.backward_pass();
+        assert_eq!(warnings.borrow().len(), 1);
+        assert!(warnings.borrow()[0].contains("1 intermediate node(s)"), "{}", warnings.borrow()[0]);
+
+        next_generation();
+        out.set_grad(1.0);
+        out.backward_pass();
+        assert_eq!(warnings.borrow().len(), 1);
+    }
+
+    #[test]
+    fn grad_node_of_x_cubed_differentiates_twice() {
+        for cube in [|x: &Node| x.clone() * x.clone() * x.clone(), |x: &Node| x.powi(3)] {
+            let x = Node::new(1.5);
+            let dy = cube(&x).grad_node(&x);
+            assert_close(dy.val(), 3.0 * 1.5 * 1.5, 1e-12);
+
+            dy.set_grad(1.0);
+            dy.backward_pass();
+            assert_close(x.grad(), 6.0 * 1.5, 1e-12);
+        }
+    }
+
+    #[test]
+    fn reset_parameters_redraws_values_in_place() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut mlp = MLP::with_rng(3, vec![4, 2], &mut rng);
+        let params = mlp.parameters();
+        let before: Vec<f64> = params.iter().map(|p| p.val()).collect();
+        for p in params.iter() {
+            p.set_grad(0.5);
+        }
+
+        mlp.reset_parameters(&mut rng);
+        let after = mlp.parameters();
+        assert_eq!(after.len(), before.len());
+        assert_eq!(mlp.num_params(), 26);
+        // the same nodes hold new values and zeroed grads
+        assert!(after.iter().zip(params.iter()).all(|(a, p)| a.ptr_eq(p)));
+        assert!(after.iter().zip(before.iter()).all(|(a, b)| a.val() != *b));
+        assert!(after.iter().all(|p| p.grad() == 0.0 && p.val().abs() < 0.1));
+    }
+
+    #[test]
+    fn exp_clamps_huge_inputs_with_zero_grad() {
+        let x = Node::new(1000.0);
+        let y = x.exp();
+        assert!(y.val().is_finite());
+        assert_eq!(y.val(), EXP_CLAMP.exp());
+
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_eq!(x.grad(), 0.0);
+    }
+
+    #[test]
+    fn pow_is_unaffected_by_the_exp_clamp() {
+        let a = Node::new(10.0);
+        let b = Node::new(30.0);
+        let y = a.pow(&b);
+        assert_close(y.val(), 1e30, 1e16);
+
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_close(a.grad(), 30.0 * 1e29, 1e16);
+        assert_close(b.grad(), 1e30 * 10f64.ln(), 1e16);
+    }
+
+    #[test]
+    fn forward_into_matches_forward_and_reuses_the_buffer() {
+        let layer = Layer::with_rng(3, 5, Activation::Tanh, &mut StdRng::seed_from_u64(2));
+        let x: Vec<Node> = [0.4, -1.0, 2.5].iter().map(|&v| Node::new(v)).collect();
+        let expected: Vec<f64> = layer.forward(x.clone()).iter().map(|n| n.val()).collect();
+
+        let mut out = Vec::new();
+        layer.forward_into(&x, &mut out);
+        assert_eq!(out.iter().map(|n| n.val()).collect::<Vec<f64>>(), expected);
+
+        // refilling clears the old outputs and keeps the allocation
+        let (ptr, capacity) = (out.as_ptr(), out.capacity());
+        layer.forward_into(&x, &mut out);
+        assert_eq!(out.len(), 5);
+        assert_eq!(out.iter().map(|n| n.val()).collect::<Vec<f64>>(), expected);
+        assert_eq!((out.as_ptr(), out.capacity()), (ptr, capacity));
+    }
+
+    #[test]
+    fn feature_importance_ignores_an_unused_feature() {
+        // the second weight is zero, so the output only depends on x0
+        let mlp = MLP::from_weights(vec![(vec![vec![0.8, 0.0]], vec![0.1])]);
+        let data = vec![
+            (vec![0.5, 3.0], vec![1.0]),
+            (vec![-1.0, -2.0], vec![0.0]),
+        ];
+        let importance = mlp.feature_importance(&data);
+        assert!(importance[0] > 0.1, "{:?}", importance);
+        assert_eq!(importance[1], 0.0);
+        assert!(mlp.parameters().iter().all(|p| p.grad() == 0.0));
+    }
+
+    #[test]
+    fn tanh_fast_stays_within_tolerance_of_tanh() {
+        for i in -600..=600 {
+            let x = i as f64 / 100.0;
+            let fast = Node::new(x).tanh_fast().val();
+            assert!((fast - x.tanh()).abs() < 0.024, "{}: {} vs {}", x, fast, x.tanh());
+        }
+        assert_eq!(Node::new(3.0).tanh_fast().val(), 1.0);
+        assert_eq!(Node::new(-50.0).tanh_fast().val(), -1.0);
+
+        // backward uses 1 - y^2 of the approximate output
+        let x = Node::new(0.5);
+        let y = x.tanh_fast();
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_close(x.grad(), 1.0 - y.val() * y.val(), 1e-12);
+    }
+
+    #[test]
+    fn dropout_only_zeroes_units_while_training() {
+        // 8 hidden units, each copied to its own output, so a dropped unit
+        // shows up as an output of exactly 0
+        let identity: Vec<Vec<f64>> = (0..8).map(|i| (0..8).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+        let mut mlp = MLP::from_weights(vec![
+            (vec![vec![1.0]; 8], vec![0.5; 8]),
+            (identity, vec![0.0; 8]),
+        ]);
+        mlp.dropout = vec![0.5];
+        let x = || vec![Node::new(1.0)];
+        let mut rng = StdRng::seed_from_u64(4);
+
+        let trained = mlp.forward_train(x(), &mut rng);
+        let dropped = trained.iter().filter(|o| o.val() == 0.0).count();
+        assert!(0 < dropped && dropped < 8, "{} of 8 dropped", dropped);
+        // survivors are scaled by 1 / (1 - p)
+        let kept = trained.iter().find(|o| o.val() != 0.0).unwrap();
+        assert_close(kept.val(), (2.0 * 1.5f64.tanh()).tanh(), 1e-12);
+
+        mlp.training = false;
+        let expected: Vec<f64> = mlp.forward(x()).iter().map(|o| o.val()).collect();
+        for _ in 0..3 {
+            let eval: Vec<f64> = mlp.forward_train(x(), &mut rng).iter().map(|o| o.val()).collect();
+            assert_eq!(eval, expected);
+        }
+        assert_eq!(mlp.predict(&[1.0]), expected);
+    }
+
+    #[test]
+    fn is_saturated_near_either_bound() {
+        assert!(Node::new(0.999).is_saturated(0.99));
+        assert!(Node::new(-0.999).is_saturated(0.99));
+        assert!(!Node::new(0.5).is_saturated(0.99));
+
+        assert!(Node::new(0.001).is_saturated_in(0.0, 1.0, 0.01));
+        assert!(Node::new(0.999).is_saturated_in(0.0, 1.0, 0.01));
+        assert!(!Node::new(0.5).is_saturated_in(0.0, 1.0, 0.01));
+    }
+
+    #[test]
+    fn product_grads_are_the_product_of_the_others() {
+        let xs: Vec<Node> = [2.0, -3.0, 0.5, 4.0].iter().map(|&v| Node::new(v)).collect();
+        let p: Node = xs.iter().product();
+        assert_eq!(p.val(), -12.0);
+        p.set_grad(1.0);
+        p.backward_pass();
+        let grads: Vec<f64> = xs.iter().map(|x| x.grad()).collect();
+        assert_eq!(grads, vec![-6.0, 4.0, -24.0, -3.0]);
+
+        let empty: Node = Vec::<Node>::new().into_iter().product();
+        assert_eq!(empty.val(), 1.0);
+    }
+
+    #[test]
+    fn grad_norm_of_known_grads() {
+        let params: Vec<Node> = (0..3).map(|_| Node::new(1.0)).collect();
+        for (p, g) in params.iter().zip([3.0, -4.0, 12.0]) {
+            p.set_grad(g);
+        }
+        assert_eq!(grad_norm(&params), 13.0);
+        assert_eq!(grad_norm(&[]), 0.0);
+    }
+
+    #[test]
+    fn relu6_values_and_grads() {
+        for (x, val, grad) in [(3.0, 3.0, 1.0), (-1.0, 0.0, 0.0), (8.0, 6.0, 0.0)] {
+            let node = Node::new(x);
+            let y = node.relu6();
+            y.set_grad(1.0);
+            y.backward_pass();
+            assert_eq!((y.val(), node.grad()), (val, grad), "relu6({})", x);
+            assert_eq!(Activation::Relu6.apply_f64(x), val);
+        }
+    }
+
+    #[test]
+    fn deep_copy_is_independent_and_keeps_sharing() {
+        let x = Node::new(2.0).with_name("x");
+        let h = x.tanh();
+        let y = h.clone() * h.clone() + x.clone();
+        let copy = y.deep_copy();
+
+        // same values, ops and structure, with the shared h still shared
+        let names = |n: &Node| n.topological_order().iter().map(|n| n.name()).collect::<Vec<String>>();
+        assert_eq!(names(&copy), names(&y));
+        assert_eq!(copy.val(), y.val());
+        assert_eq!(copy.graph_size(), y.graph_size());
+        let product = &copy.children()[0];
+        assert!(product.children()[0].ptr_eq(&product.children()[1]));
+
+        // no node is shared with the original
+        let originals = y.topological_order();
+        assert!(copy.topological_order().iter().all(|c| !originals.iter().any(|o| o.ptr_eq(c))));
+
+        x.set_val(-5.0);
+        let copied_x = &copy.children()[1];
+        assert_eq!(copied_x.val(), 2.0);
+        copy.set_grad(1.0);
+        copy.backward_pass();
+        assert_eq!(x.grad(), 0.0);
+        assert_close(copied_x.grad(), 1.0 + 2.0 * 2f64.tanh() * (1.0 - 2f64.tanh().powi(2)), 1e-12);
+    }
+
+    #[test]
+    fn residual_with_tiny_inner_weights_is_near_identity() {
+        let inner = Layer::from_weights(vec![vec![1e-6, -1e-6, 0.0]; 3], vec![0.0; 3]);
+        let residual = Residual::new(inner);
+        let x = [0.5, -2.0, 3.0];
+        let out = residual.forward(x.iter().map(|&v| Node::new(v)).collect());
+        for ((o, p), xi) in out.iter().zip(residual.predict(&x)).zip(x.iter()) {
+            assert_close(o.val(), *xi, 1e-5);
+            assert_eq!(o.val(), p);
+        }
+
+        // the skip path passes gradients straight through
+        let input = Node::new(1.0);
+        let out = residual.forward(vec![input.clone(), Node::new(0.0), Node::new(0.0)]);
+        out[0].set_grad(1.0);
+        out[0].backward_pass();
+        assert_close(input.grad(), 1.0, 1e-5);
+    }
+
+    #[test]
+    fn params_round_trip_through_a_flat_vec() {
+        let mlp = MLP::with_rng(2, vec![3, 1], &mut StdRng::seed_from_u64(6));
+        let params = mlp.parameters();
+        let values = params_to_vec(&params);
+        assert_eq!(values.len(), mlp.num_params());
+        let before = mlp.predict(&[0.3, -0.7]);
+
+        let shifted: Vec<f64> = values.iter().map(|v| v + 0.5).collect();
+        params_from_vec(&params, &shifted);
+        assert_eq!(params_to_vec(&mlp.parameters()), shifted);
+        assert_ne!(mlp.predict(&[0.3, -0.7]), before);
+
+        params_from_vec(&params, &values);
+        assert_eq!(mlp.predict(&[0.3, -0.7]), before);
+    }
+
+    #[test]
+    fn try_grad_is_none_until_backward_reaches_the_node() {
+        let x = Node::new(3.0);
+        let y = x.square();
+        assert_eq!(x.try_grad(), None);
+
+        y.set_grad(1.0);
+        y.backward_pass();
+        assert_eq!(x.try_grad(), Some(6.0));
+        assert_eq!(y.try_grad(), Some(1.0));
+
+        // an unrelated node backward never touched
+        assert_eq!(Node::new(1.0).try_grad(), None);
+        x.set_grad(0.0);
+        assert_eq!(x.try_grad(), None);
+        next_generation();
+        assert_eq!(y.try_grad(), None);
+    }
+
+    #[test]
+    fn forward_timed_matches_forward() {
+        let mlp = MLP::with_rng(3, vec![4, 2], &mut StdRng::seed_from_u64(8));
+        let x = || [0.1, -0.4, 0.9].iter().map(|&v| Node::new(v)).collect::<Vec<Node>>();
+        let (timed, elapsed) = mlp.forward_timed(x());
+        let plain: Vec<f64> = mlp.forward(x()).iter().map(|o| o.val()).collect();
+        assert_eq!(timed.iter().map(|o| o.val()).collect::<Vec<f64>>(), plain);
+        assert!(elapsed < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn shared_weight_gets_the_sum_of_its_uses() {
+        let w = Node::new(0.5);
+        let (x1, x2) = (Node::new(2.0), Node::new(-3.0));
+        let y1 = (w.clone() * x1).tanh();
+        let y2 = (w.clone() * x2).tanh();
+        let loss = y1.clone() + y2.clone();
+
+        loss.set_grad(1.0);
+        loss.backward_pass();
+        let expected = (1.0 - y1.val() * y1.val()) * 2.0 + (1.0 - y2.val() * y2.val()) * -3.0;
+        assert_close(w.grad(), expected, 1e-12);
+    }
+
+    #[test]
+    fn visit_reaches_each_node_of_a_diamond_once() {
+        // x feeds both a and b, which meet again in y
+        let x = Node::new(0.5).with_name("x");
+        let a = x.tanh().with_name("a");
+        let b = x.exp().with_name("b");
+        let y = (a * b).with_name("y");
+
+        let mut names = Vec::new();
+        y.visit(|node| names.push(node.name()));
+        assert_eq!(names.len(), 4);
+        assert_eq!(names.iter().filter(|n| *n == "x").count(), 1);
+        // children before parents, the root last
+        assert_eq!(names[0], "x");
+        assert_eq!(names[3], "y");
+    }
+
+    #[test]
+    fn fold_constants_collapses_constant_subgraphs() {
+        let x = Node::new(1.5).with_name("x");
+        let y = Node::constant(2.0) * Node::constant(3.0) + x.clone();
+        let folded = y.fold_constants();
+
+        assert_eq!(y.to_expr(), "(2 * 3) + x");
+        assert_eq!(folded.to_expr(), "6 + x");
+        assert_eq!((y.graph_size(), folded.graph_size()), (5, 3));
+        assert_eq!(folded.val(), y.val());
+        let six = &folded.children()[0];
+        assert!(six.is_constant());
+        assert_eq!(six.val(), 6.0);
+
+        // x is kept as the same node, so gradients still reach it
+        folded.set_grad(1.0);
+        folded.backward_pass();
+        assert!(folded.children()[1].ptr_eq(&x));
+        assert_eq!(x.grad(), 1.0);
+        // nothing to fold hands back the same node
+        assert!(x.tanh().fold_constants().children()[0].ptr_eq(&x));
+    }
+
+    #[test]
+    fn forward_with_overrides_the_activation_for_one_call() {
+        let neuron = Neuron::from_weights(vec![1.0, -2.0], 0.5);
+        let x: Vec<Node> = [1.0, 1.5].iter().map(|&v| Node::new(v)).collect();
+        // w.x + b = -1.5
+        assert_close(neuron.forward_with(&x, &Activation::Tanh).val(), (-1.5f64).tanh(), 1e-12);
+        assert_eq!(neuron.forward_with(&x, &Activation::Relu).val(), 0.0);
+        assert_eq!(neuron.forward_with(&x, &Activation::Linear).val(), -1.5);
+        // the neuron's own activation is unchanged
+        assert_eq!(neuron.act, Activation::Tanh);
+        assert_eq!(neuron.forward(&x).val(), neuron.forward_with(&x, &Activation::Tanh).val());
+    }
+
+    #[test]
+    fn to_expr_prints_a_neuron_with_named_nodes() {
+        let w0 = Node::new(0.3).with_name("w0");
+        let x0 = Node::new(2.0).with_name("x0");
+        let b = Node::new(0.1).with_name("b");
+        assert_eq!(w0.mul_add(&x0, &b).tanh().to_expr(), "tanh((w0 * x0) + b)");
+        assert_eq!((w0.clone() * x0.clone() + b.clone()).tanh().to_expr(), "tanh((w0 * x0) + b)");
+
+        // unnamed leaves print their value, a named intermediate its name
+        let z = (x0.clone() - Node::new(1.5)).with_name("z");
+        assert_eq!((z.powi(2) * w0.clone()).to_expr(), "(z^2) * w0");
+        assert_eq!(x0.clamp(-1.0, 1.0).to_expr(), "clamp(x0, -1, 1)");
+        assert_eq!(x0.pow(&w0).to_expr(), "x0^w0");
+    }
+
+    #[test]
+    fn backward_flat_matches_grads_and_zeroes_unused_params() {
+        let a = Node::new(2.0);
+        let b = Node::new(3.0);
+        let unused = Node::new(1.0);
+        unused.set_grad(7.0);
+        let loss = a.clone() * b.clone();
+
+        let flat = backward_flat(&loss, &[a.clone(), b.clone(), unused.clone()]);
+        assert_eq!(flat, vec![3.0, 2.0, 0.0]);
+        assert_eq!(flat, vec![a.grad(), b.grad(), unused.grad()]);
     }
 }
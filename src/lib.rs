@@ -0,0 +1,9 @@
+pub mod activations;
+pub mod arena;
+pub mod data;
+pub mod grad;
+pub mod loss;
+pub mod optim;
+pub mod trainer;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
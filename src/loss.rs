@@ -0,0 +1,352 @@
+use std::fmt;
+use crate::grad::{dot, log_softmax, Node};
+
+// keeps the norms away from zero so the division stays finite
+const NORM_EPS: f64 = 1e-12;
+// keeps BceLoss's probabilities away from 0 and 1
+const BCE_EPS: f64 = 1e-7;
+
+// ============= REDUCTION =============
+// how per-element losses are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reduction {
+    #[default]
+    Mean,
+    Sum,
+    // keep one loss node per element, e.g. to weight examples individually
+    None,
+}
+
+// a reduced loss: one node for Mean and Sum, one per element for None
+#[derive(Debug, Clone)]
+pub enum Reduced {
+    Scalar(Node),
+    Elements(Vec<Node>),
+}
+
+impl Reduced {
+    // the single loss node; panics for Reduction::None
+    pub fn scalar(self) -> Node {
+        match self {
+            Reduced::Scalar(loss) => loss,
+            Reduced::Elements(_) => panic!("loss was computed with Reduction::None, there is no single scalar"),
+        }
+    }
+
+    // per-element losses, or the scalar as a one-element vec
+    pub fn elements(self) -> Vec<Node> {
+        match self {
+            Reduced::Scalar(loss) => vec![loss],
+            Reduced::Elements(losses) => losses,
+        }
+    }
+}
+
+impl Reduction {
+    pub fn apply(self, losses: Vec<Node>) -> Reduced {
+        match self {
+            Reduction::None => Reduced::Elements(losses),
+            Reduction::Sum => Reduced::Scalar(losses.into_iter().sum()),
+            Reduction::Mean => {
+                assert!(!losses.is_empty(), "mean reduction of an empty loss");
+                let n = losses.len() as f64;
+                Reduced::Scalar(losses.into_iter().sum::<Node>() * Node::constant(1.0 / n))
+            }
+        }
+    }
+
+    // like apply, with each element's loss scaled by its weight first. Mean still
+    // divides by the number of elements, not the total weight, so a weight of
+    // 2.0 counts an example exactly twice. combine with Reduction::None to
+    // weight examples, e.g.
+    // `Reduction::Mean.apply_weighted(cross_entropy(.., Reduction::None).elements(), &w)`
+    pub fn apply_weighted(self, losses: Vec<Node>, sample_weights: &[f64]) -> Reduced {
+        assert_eq!(
+            losses.len(), sample_weights.len(),
+            "got {} sample weights for {} losses", sample_weights.len(), losses.len()
+        );
+        let weighted = losses.into_iter()
+            .zip(sample_weights.iter())
+            .map(|(loss, &w)| loss * Node::constant(w))
+            .collect();
+        self.apply(weighted)
+    }
+}
+
+// ============= LOSSES =============
+// squared error per output against constant targets
+pub fn mse(outputs: &[Node], targets: &[f64], reduction: Reduction) -> Reduced {
+    assert_eq!(outputs.len(), targets.len(), "mse: length mismatch");
+    let errors = outputs.iter()
+        .zip(targets.iter())
+        .map(|(o, &t)| (o.clone() - Node::constant(t)).square())
+        .collect();
+    reduction.apply(errors)
+}
+
+// absolute error per output against constant targets
+pub fn mae(outputs: &[Node], targets: &[f64], reduction: Reduction) -> Reduced {
+    assert_eq!(outputs.len(), targets.len(), "mae: length mismatch");
+    let errors = outputs.iter()
+        .zip(targets.iter())
+        .map(|(o, &t)| (o.clone() - Node::constant(t)).abs())
+        .collect();
+    reduction.apply(errors)
+}
+
+// 1 - cos(a, b); gradients flow into `a`, `b` is treated as a constant target
+pub fn cosine_loss(a: &[Node], b: &[f64]) -> Node {
+    assert_eq!(a.len(), b.len(), "cosine_loss: length mismatch");
+    let b: Vec<Node> = b.iter().map(|&v| Node::constant(v)).collect();
+
+    let norm_a = (dot(a, a) + Node::constant(NORM_EPS)).sqrt();
+    let norm_b = (dot(&b, &b) + Node::constant(NORM_EPS)).sqrt();
+    let cos = dot(a, &b) * (norm_a * norm_b).recip();
+
+    Node::constant(1.0) - cos
+}
+
+// cross-entropy of softmax(row) against its target class, one row of logits and
+// one target per example, with label smoothing: the target distribution puts
+// 1 - eps on the true class and eps / (K - 1) on every other one. eps = 0 is the
+// usual -ln(softmax(row)[target])
+pub fn cross_entropy(
+    logits: &[Vec<Node>],
+    targets: &[usize],
+    label_smoothing: f64,
+    reduction: Reduction,
+) -> Reduced {
+    assert_eq!(logits.len(), targets.len(), "cross_entropy: need one target per row of logits");
+    let losses = logits.iter()
+        .zip(targets.iter())
+        .map(|(row, &target)| example_cross_entropy(row, target, label_smoothing))
+        .collect();
+    reduction.apply(losses)
+}
+
+fn example_cross_entropy(logits: &[Node], target: usize, label_smoothing: f64) -> Node {
+    let k = logits.len();
+    assert!(target < k, "cross_entropy: target {} out of range for {} classes", target, k);
+    assert!((0.0..1.0).contains(&label_smoothing), "cross_entropy: label_smoothing must be in [0, 1)");
+    assert!(k > 1 || label_smoothing == 0.0, "cross_entropy: label smoothing needs at least 2 classes");
+
+    let log_probs = log_softmax(logits);
+    let off_target = if k > 1 { label_smoothing / (k - 1) as f64 } else { 0.0 };
+
+    let mut loss = Node::constant(0.0);
+    for (i, log_p) in log_probs.into_iter().enumerate() {
+        let q = if i == target { 1.0 - label_smoothing } else { off_target };
+        if q != 0.0 {
+            loss = loss - Node::constant(q) * log_p;
+        }
+    }
+    loss
+}
+
+// binary cross-entropy of sigmoid(logit) against `target` in [0, 1], computed
+// straight from the logit as softplus(x) - x * t, which is the stable
+// max(x, 0) - x * t + ln(1 + e^-|x|). the gradient is sigmoid(x) - t
+pub fn bce_with_logits(logit: &Node, target: f64) -> Node {
+    logit.softplus() - logit.clone() * Node::constant(target)
+}
+
+// ============= LOSS TRAIT =============
+// a per-example loss the trainer can be configured with: the model's outputs
+// for one example against its targets, reduced to a single node
+pub trait Loss: fmt::Debug + Send + Sync {
+    fn forward(&self, outputs: &[Node], targets: &[f64]) -> Node;
+
+    // the loss's value for plain f64 outputs, e.g. from MLP::predict
+    fn eval(&self, outputs: &[f64], targets: &[f64]) -> f64 {
+        let outputs: Vec<Node> = outputs.iter().map(|&o| Node::constant(o)).collect();
+        self.forward(&outputs, targets).val()
+    }
+}
+
+// mean squared error over the outputs; the trainer's default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MseLoss;
+impl Loss for MseLoss {
+    fn forward(&self, outputs: &[Node], targets: &[f64]) -> Node {
+        mse(outputs, targets, Reduction::Mean).scalar()
+    }
+}
+
+// binary cross-entropy averaged over the outputs, which must already be
+// probabilities; they're clamped to [BCE_EPS, 1 - BCE_EPS] so ln stays finite
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BceLoss;
+impl Loss for BceLoss {
+    fn forward(&self, outputs: &[Node], targets: &[f64]) -> Node {
+        assert_eq!(outputs.len(), targets.len(), "bce: length mismatch");
+        let terms = outputs.iter()
+            .zip(targets.iter())
+            .map(|(o, &t)| {
+                let p = o.clamp(BCE_EPS, 1.0 - BCE_EPS);
+                let one_minus_p = Node::constant(1.0) - p.clone();
+                Node::constant(-t) * p.ln() - Node::constant(1.0 - t) * one_minus_p.ln()
+            })
+            .collect();
+        Reduction::Mean.apply(terms).scalar()
+    }
+}
+
+// cross-entropy of softmax(outputs) against a target distribution, usually
+// one-hot. the outputs are logits, so pair it with a linear output layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CrossEntropyLoss;
+impl Loss for CrossEntropyLoss {
+    fn forward(&self, outputs: &[Node], targets: &[f64]) -> Node {
+        assert_eq!(outputs.len(), targets.len(), "cross entropy: need one target probability per logit");
+        log_softmax(outputs)
+            .into_iter()
+            .zip(targets.iter())
+            .filter(|(_, &t)| t != 0.0)
+            .fold(Node::constant(0.0), |acc, (log_p, &t)| acc - Node::constant(t) * log_p)
+    }
+}
+
+// ============= PENALTIES =============
+// sum of |p| over `params`, to be scaled by a coefficient and added to the
+// loss: `loss + Node::constant(coef) * l1_penalty(&mlp.parameters())`.
+// each parameter then gets coef * sign(p) added to its grad
+pub fn l1_penalty(params: &[Node]) -> Node {
+    params.iter().map(|p| p.abs()).sum()
+}
+
+// 0.5 * sum of p^2 over `params`, built with square so its gradient, p itself,
+// flows through backward like the rest of the loss. scaled by coef this gives
+// each parameter coef * p of extra grad; unlike decoupled weight decay the
+// optimizer sees it as part of the gradient
+pub fn l2_penalty(params: &[Node]) -> Node {
+    let sum: Node = params.iter().map(|p| p.square()).sum();
+    Node::constant(0.5) * sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    fn nodes(vals: &[f64]) -> Vec<Node> {
+        vals.iter().map(|&v| Node::new(v)).collect()
+    }
+
+    #[test]
+    fn cosine_loss_of_aligned_vectors_is_zero() {
+        assert!(cosine_loss(&nodes(&[1.0, 2.0, 3.0]), &[2.0, 4.0, 6.0]).val().abs() < 1e-12);
+        assert!((cosine_loss(&nodes(&[1.0, 0.0]), &[0.0, 1.0]).val() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn cosine_loss_grad_matches_finite_differences() {
+        let (a, b) = ([0.3, -1.2, 0.8], [0.5, -1.0, 2.0]);
+        let inputs = nodes(&a);
+        let loss = cosine_loss(&inputs, &b);
+        loss.set_grad(1.0);
+        loss.backward_pass();
+
+        let h = 1e-5;
+        for i in 0..a.len() {
+            let shifted = |d: f64| {
+                let mut x = a;
+                x[i] += d;
+                cosine_loss(&nodes(&x), &b).val()
+            };
+            let numeric = (shifted(h) - shifted(-h)) / (2.0 * h);
+            assert!((inputs[i].grad() - numeric).abs() < 1e-8, "input {}: {} vs {}", i, inputs[i].grad(), numeric);
+        }
+    }
+
+    #[test]
+    fn cross_entropy_label_smoothing() {
+        let loss = |logits: &[Node], eps: f64| {
+            cross_entropy(&[logits.to_vec()], &[0], eps, Reduction::Mean).scalar().val()
+        };
+        let logits = nodes(&[2.0, -1.0, 0.5]);
+        let plain = loss(&logits, 0.0);
+        assert_close(plain, -log_softmax(&logits)[0].val(), 1e-12);
+
+        // a confident correct prediction costs nothing without smoothing...
+        let confident = nodes(&[30.0, 0.0, 0.0]);
+        assert!(loss(&confident, 0.0) < 1e-12);
+        // ...but the smoothed target still puts mass on the other classes
+        let smoothed = loss(&confident, 0.1);
+        assert!(smoothed > 1.0, "{}", smoothed);
+        assert!(loss(&logits, 0.1) > plain);
+    }
+
+    #[test]
+    fn l1_penalty_adds_plus_minus_coef_to_each_grad() {
+        let params = nodes(&[0.5, -2.0, 0.0]);
+        let penalty = Node::constant(0.1) * l1_penalty(&params);
+        assert_close(penalty.val(), 0.25, 1e-12);
+        penalty.set_grad(1.0);
+        penalty.backward_pass();
+        let grads: Vec<f64> = params.iter().map(|p| p.grad()).collect();
+        // zero takes the subgradient 0
+        assert_eq!(grads, vec![0.1, -0.1, 0.0]);
+    }
+
+    #[test]
+    fn l2_penalty_grad_is_coef_times_the_weight() {
+        let params = nodes(&[0.5, -2.0, 3.0]);
+        let penalty = Node::constant(0.1) * l2_penalty(&params);
+        assert_close(penalty.val(), 0.05 * (0.25 + 4.0 + 9.0), 1e-12);
+        penalty.set_grad(1.0);
+        penalty.backward_pass();
+        for p in params.iter() {
+            assert_close(p.grad(), 0.1 * p.val(), 1e-12);
+        }
+    }
+
+    #[test]
+    fn reductions_agree_with_each_other() {
+        let outputs = nodes(&[0.5, -1.0, 2.0, 0.0]);
+        let targets = [1.0, 1.0, 0.5, -0.5];
+        let mean = mse(&outputs, &targets, Reduction::Mean).scalar().val();
+        let sum = mse(&outputs, &targets, Reduction::Sum).scalar().val();
+        assert_close(sum, mean * 4.0, 1e-12);
+
+        let elements = mse(&outputs, &targets, Reduction::None).elements();
+        assert_eq!(elements.len(), 4);
+        assert_close(elements.iter().map(|e| e.val()).sum(), sum, 1e-12);
+        assert_eq!(Reduction::default(), Reduction::Mean);
+
+        let logits = vec![nodes(&[2.0, -1.0]), nodes(&[0.0, 0.5]), nodes(&[1.0, 1.0])];
+        let classes = [0, 1, 1];
+        let ce = |reduction| cross_entropy(&logits, &classes, 0.0, reduction);
+        assert_close(ce(Reduction::Sum).scalar().val(), ce(Reduction::Mean).scalar().val() * 3.0, 1e-12);
+        assert_eq!(ce(Reduction::None).elements().len(), 3);
+    }
+
+    #[test]
+    fn doubling_a_sample_weight_doubles_its_grad() {
+        let grads = |w: &[f64]| {
+            let outputs = nodes(&[0.5, -1.0]);
+            let losses = mse(&outputs, &[1.0, 1.0], Reduction::None).elements();
+            let loss = Reduction::Mean.apply_weighted(losses, w).scalar();
+            loss.set_grad(1.0);
+            loss.backward_pass();
+            outputs.iter().map(|o| o.grad()).collect::<Vec<f64>>()
+        };
+        let base = grads(&[1.0, 1.0]);
+        let doubled = grads(&[2.0, 1.0]);
+        assert_close(doubled[0], 2.0 * base[0], 1e-12);
+        assert_close(doubled[1], base[1], 1e-12);
+    }
+
+    #[test]
+    fn bce_with_logits_grad_is_sigmoid_minus_target() {
+        for (x, t) in [(0.7, 1.0), (-1.5, 0.0), (0.0, 0.3), (1000.0, 0.0), (-1000.0, 1.0)] {
+            let logit = Node::new(x);
+            let loss = bce_with_logits(&logit, t);
+            loss.set_grad(1.0);
+            loss.backward_pass();
+            assert!(loss.val().is_finite() && logit.grad().is_finite(), "x = {}", x);
+            assert_close(logit.grad(), 1.0 / (1.0 + f64::exp(-x)) - t, 1e-12);
+        }
+        // a confidently wrong logit costs about |x|
+        assert_close(bce_with_logits(&Node::new(1000.0), 0.0).val(), 1000.0, 1e-9);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::grad::Node;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// ============= MOMENTUM =============
+// SGD with a per-parameter velocity:
+//   v = beta * v + g
+//   w -= lr * v                (classical)
+//   w -= lr * (g + beta * v)   (nesterov, evaluates the step at the look-ahead point)
+#[derive(Debug, Clone)]
+pub struct Momentum {
+    params: Vec<Node>,
+    pub lr: f64,
+    pub beta: f64,
+    pub nesterov: bool,
+    // std of gaussian noise added to every gradient before the update, 0 disables it
+    pub grad_noise_std: f64,
+    // shrink the noise over time as std / (1 + t)^0.55
+    pub anneal_noise: bool,
+    // source of the gradient noise; replace with a seeded StdRng for reproducible runs
+    pub rng: StdRng,
+    velocity: Vec<f64>,
+    t: u64,
+}
+
+impl Momentum {
+    pub fn new(params: Vec<Node>, lr: f64, beta: f64) -> Momentum {
+        let velocity = vec![0.0; params.len()];
+        Momentum {
+            params,
+            lr,
+            beta,
+            nesterov: false,
+            grad_noise_std: 0.0,
+            anneal_noise: false,
+            rng: StdRng::from_entropy(),
+            velocity,
+            t: 0,
+        }
+    }
+
+    pub fn step(&mut self) {
+        let noise_std = if self.anneal_noise {
+            self.grad_noise_std / (1.0 + self.t as f64).powf(0.55)
+        } else {
+            self.grad_noise_std
+        };
+        self.t += 1;
+
+        for (param, v) in self.params.iter().zip(self.velocity.iter_mut()) {
+            let mut g = param.grad();
+            // only touch the rng when noise is on, so std = 0 is exactly the noiseless update
+            if noise_std > 0.0 {
+                g += noise_std * standard_normal(&mut self.rng);
+            }
+            *v = self.beta * *v + g;
+
+            let update = if self.nesterov { g + self.beta * *v } else { *v };
+            param.set_val(param.val() - self.lr * update);
+        }
+    }
+
+    pub fn zero_grad(&self) {
+        for param in self.params.iter() {
+            param.set_grad(0.0);
+        }
+    }
+}
+
+// ============= RMSPROP =============
+// scales each step by a running average of that parameter's squared grads:
+//   s = alpha * s + (1 - alpha) * g^2
+//   w -= lr * g / (sqrt(s) + eps)
+// so coordinates with consistently large gradients take proportionally smaller steps
+#[derive(Debug, Clone)]
+pub struct RMSprop {
+    params: Vec<Node>,
+    pub lr: f64,
+    pub alpha: f64,
+    pub eps: f64,
+    sq_avg: Vec<f64>,
+}
+
+impl RMSprop {
+    pub fn new(params: Vec<Node>, lr: f64, alpha: f64, eps: f64) -> RMSprop {
+        let sq_avg = vec![0.0; params.len()];
+        RMSprop {
+            params,
+            lr,
+            alpha,
+            eps,
+            sq_avg,
+        }
+    }
+
+    pub fn step(&mut self) {
+        for (param, s) in self.params.iter().zip(self.sq_avg.iter_mut()) {
+            let g = param.grad();
+            *s = self.alpha * *s + (1.0 - self.alpha) * g * g;
+            param.set_val(param.val() - self.lr * g / (s.sqrt() + self.eps));
+        }
+    }
+
+    pub fn zero_grad(&self) {
+        for param in self.params.iter() {
+            param.set_grad(0.0);
+        }
+    }
+}
+
+// box-muller; rand 0.8 only ships uniform distributions without rand_distr
+pub(crate) fn standard_normal(rng: &mut impl Rng) -> f64 {
+    // 1 - [0, 1) keeps u1 away from 0 so ln stays finite
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // w after each of `steps` momentum steps on f(w) = w^2 / 2, whose grad is w
+    fn trajectory(nesterov: bool, steps: usize) -> Vec<f64> {
+        let w = Node::new(1.0);
+        let mut opt = Momentum::new(vec![w.clone()], 0.1, 0.9);
+        opt.nesterov = nesterov;
+        (0..steps)
+            .map(|_| {
+                w.set_grad(w.val());
+                opt.step();
+                w.val()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nesterov_looks_ahead_on_a_quadratic() {
+        let classical = trajectory(false, 30);
+        let nesterov = trajectory(true, 30);
+        // first step: v = 1, then 1 - 0.1 * 1 vs 1 - 0.1 * (1 + 0.9 * 1)
+        assert!((classical[0] - 0.9).abs() < 1e-12);
+        assert!((nesterov[0] - 0.81).abs() < 1e-12);
+        // the look-ahead damps the oscillation
+        assert!(nesterov[29].abs() < classical[29].abs());
+    }
+
+    // w after one step from 1.0 with grad 0.5 and the given noise
+    fn noisy_step(std: f64, seed: u64) -> f64 {
+        let w = Node::new(1.0);
+        let mut opt = Momentum::new(vec![w.clone()], 0.1, 0.9);
+        opt.grad_noise_std = std;
+        opt.rng = StdRng::seed_from_u64(seed);
+        w.set_grad(0.5);
+        opt.step();
+        w.val()
+    }
+
+    #[test]
+    fn gradient_noise_is_seeded_and_off_at_zero() {
+        assert_eq!(noisy_step(0.0, 1), 1.0 - 0.1 * 0.5);
+        assert_ne!(noisy_step(1.0, 1), noisy_step(0.0, 1));
+        assert_eq!(noisy_step(1.0, 1), noisy_step(1.0, 1));
+        assert_ne!(noisy_step(1.0, 1), noisy_step(1.0, 2));
+    }
+
+    #[test]
+    fn rmsprop_handles_a_poorly_scaled_quadratic() {
+        // f = (100 a^2 + 0.01 b^2) / 2: an lr that's stable for a barely moves b
+        let grads = |a: &Node, b: &Node| {
+            a.set_grad(100.0 * a.val());
+            b.set_grad(0.01 * b.val());
+        };
+        let (a, b) = (Node::new(1.0), Node::new(1.0));
+        let mut opt = RMSprop::new(vec![a.clone(), b.clone()], 0.01, 0.9, 1e-8);
+        for _ in 0..100 {
+            grads(&a, &b);
+            opt.step();
+        }
+
+        let (sgd_a, sgd_b) = (Node::new(1.0), Node::new(1.0));
+        for _ in 0..100 {
+            grads(&sgd_a, &sgd_b);
+            for p in [&sgd_a, &sgd_b] {
+                p.set_val(p.val() - 0.01 * p.grad());
+            }
+        }
+
+        assert!(sgd_b.val() > 0.98);
+        assert!(b.val() < 0.1, "{}", b.val());
+        assert!(a.val().abs() < 0.1, "{}", a.val());
+    }
+}
@@ -0,0 +1,34 @@
+// helpers for writing autodiff tests, enabled with the `testing` feature
+// (and always available to the crate's own unit tests)
+use crate::grad::Node;
+
+#[track_caller]
+pub fn assert_close(a: f64, b: f64, eps: f64) {
+    assert!(
+        (a - b).abs() <= eps,
+        "assert_close failed: {} and {} differ by {} (eps = {})", a, b, (a - b).abs(), eps
+    );
+}
+
+// compares a node's value against an expected f64
+#[track_caller]
+pub fn assert_node_close(node: &Node, expected: f64, eps: f64) {
+    assert_close(node.val(), expected, eps);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assert_close_passes_within_eps() {
+        assert_close(1.0, 1.0 + 1e-9, 1e-6);
+        assert_node_close(&Node::new(2.5), 2.5, 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "assert_close failed")]
+    fn assert_close_panics_outside_eps() {
+        assert_close(1.0, 1.1, 1e-6);
+    }
+}
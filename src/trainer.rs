@@ -0,0 +1,741 @@
+use crate::grad::{params_from_vec, params_to_vec, MLP, Node};
+use crate::loss::{Loss, MseLoss};
+use crate::optim::standard_normal;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+// where the trainer's progress lines go. stdout by default; replace it to
+// collect the lines or send them somewhere else
+#[derive(Clone)]
+pub struct Logger(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl Logger {
+    pub fn new(f: impl Fn(&str) + Send + Sync + 'static) -> Logger {
+        Logger(Arc::new(f))
+    }
+
+    pub fn stdout() -> Logger {
+        Logger::new(|line| println!("{}", line))
+    }
+
+    fn log(&self, line: &str) {
+        (self.0)(line)
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Logger {
+        Logger::stdout()
+    }
+}
+
+impl fmt::Debug for Logger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Logger")
+    }
+}
+
+// per-epoch losses recorded by a training run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    pub train_losses: Vec<f64>,
+    // empty unless the run had a validation set
+    pub val_losses: Vec<f64>,
+    // one entry per update step, holding ||update|| / ||params|| for each
+    // layer. empty unless Trainer::track_update_ratios was set
+    pub update_ratios: Vec<Vec<f64>>,
+}
+
+impl History {
+    // writes one `epoch,train_loss,val_loss` row per epoch under a header line,
+    // epochs counted from 0. the val_loss column is left out if there are no
+    // validation losses
+    pub fn to_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        if self.val_losses.is_empty() {
+            writeln!(out, "epoch,train_loss")?;
+            for (epoch, train) in self.train_losses.iter().enumerate() {
+                writeln!(out, "{},{}", epoch, train)?;
+            }
+        } else {
+            writeln!(out, "epoch,train_loss,val_loss")?;
+            for (epoch, (train, val)) in self.train_losses.iter().zip(self.val_losses.iter()).enumerate() {
+                writeln!(out, "{},{},{}", epoch, train, val)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+// ============= TRAINER =============
+// owns the model and runs plain mini-batch SGD on a configurable loss
+#[derive(Debug, Clone)]
+pub struct Trainer {
+    pub mlp: MLP,
+    pub learning_rate: f64,
+    pub lr_decay: f64,
+    // examples per update step; the last batch of an epoch may be smaller
+    pub batch_size: usize,
+    // gradient accumulation: backward runs on this many batches before each
+    // update, with every batch's gradient weighted by its share of the
+    // examples so the step matches one batch of batch_size * accumulation_steps
+    pub accumulation_steps: usize,
+    // stop early once the average epoch loss drops below this
+    pub target_loss: f64,
+    // log the average loss every `log_every` epochs
+    pub verbose: bool,
+    pub log_every: usize,
+    pub logger: Logger,
+    // outputs above this count as class 1 in evaluate
+    pub threshold: f64,
+    // used for shuffling; replace with a seeded StdRng for reproducible runs
+    pub rng: StdRng,
+    // with a validation set, end training with the weights from the epoch
+    // that had the lowest validation loss instead of the last one
+    pub restore_best: bool,
+    // what fit minimizes, per example. evaluate and the validation losses
+    // report the same loss
+    pub loss: Arc<dyn Loss>,
+    // record each layer's update-to-weight ratio at every step in the history.
+    // around 1e-3 is healthy; much larger or smaller suggests the lr is off
+    pub track_update_ratios: bool,
+}
+
+impl Trainer {
+    pub fn new(mlp: MLP, learning_rate: f64) -> Trainer {
+        Trainer {
+            mlp,
+            learning_rate,
+            lr_decay: 0.0,
+            batch_size: 1,
+            accumulation_steps: 1,
+            target_loss: 0.0,
+            verbose: false,
+            log_every: 1,
+            logger: Logger::stdout(),
+            threshold: 0.5,
+            rng: StdRng::from_entropy(),
+            restore_best: false,
+            loss: Arc::new(MseLoss),
+            track_update_ratios: false,
+        }
+    }
+
+    // trains for up to `epochs` epochs and hands back the trained model along
+    // with the average loss of each epoch, e.g.
+    // `let (model, history) = Trainer::new(mlp, 0.03).fit(&data, 100);`
+    pub fn fit(mut self, data: &[(Vec<f64>, Vec<f64>)], epochs: usize) -> (MLP, History) {
+        let history = self.run(data, None, epochs);
+        (self.mlp, history)
+    }
+
+    // like fit, but also evaluates `val` after every epoch. both loss series
+    // in the history have one entry per epoch actually run
+    pub fn fit_with_validation(
+        mut self,
+        data: &[(Vec<f64>, Vec<f64>)],
+        val: &[(Vec<f64>, Vec<f64>)],
+        epochs: usize,
+    ) -> (MLP, History) {
+        let history = self.run(data, Some(val), epochs);
+        (self.mlp, history)
+    }
+
+    fn run(
+        &mut self,
+        data: &[(Vec<f64>, Vec<f64>)],
+        val: Option<&[(Vec<f64>, Vec<f64>)]>,
+        epochs: usize,
+    ) -> History {
+        let mut losses = Vec::new();
+        let mut val_losses = Vec::new();
+        let mut update_ratios = Vec::new();
+        // (val loss, parameter values) of the best epoch so far
+        let mut best: Option<(f64, Vec<f64>)> = None;
+
+        for epoch in 0..epochs {
+            let learning_rate = self.learning_rate / (1.0 + epoch as f64 * self.lr_decay);
+            let avg_loss = self.train_epoch(data, learning_rate, &mut update_ratios);
+            losses.push(avg_loss);
+
+            if let Some(val) = val {
+                let (val_loss, _) = self.evaluate(val);
+                val_losses.push(val_loss);
+                if self.restore_best && best.as_ref().is_none_or(|(best_loss, _)| val_loss < *best_loss) {
+                    best = Some((val_loss, params_to_vec(&self.mlp.parameters())));
+                }
+                if self.should_log(epoch) {
+                    self.logger.log(&format!("Epoch {}: Average loss = {:.4}, val loss = {:.4} (lr = {:.4})", epoch, avg_loss, val_loss, learning_rate));
+                }
+            } else if self.should_log(epoch) {
+                self.logger.log(&format!("Epoch {}: Average loss = {:.4} (lr = {:.4})", epoch, avg_loss, learning_rate));
+            }
+
+            if avg_loss < self.target_loss {
+                if self.verbose {
+                    self.logger.log(&format!("Reached target loss at epoch {}", epoch));
+                }
+                break;
+            }
+        }
+
+        if let Some((_, vals)) = best {
+            params_from_vec(&self.mlp.parameters(), &vals);
+        }
+        History { train_losses: losses, val_losses, update_ratios }
+    }
+
+    // one shuffled pass of mini-batch SGD, returns the average loss per example.
+    // update ratios, if tracked, are appended to `update_ratios`
+    fn train_epoch(
+        &mut self,
+        data: &[(Vec<f64>, Vec<f64>)],
+        learning_rate: f64,
+        update_ratios: &mut Vec<Vec<f64>>,
+    ) -> f64 {
+        let mut total_loss = 0.0;
+
+        let mut indices: Vec<usize> = (0..data.len()).collect();
+        indices.shuffle(&mut self.rng);
+
+        let batches: Vec<&[usize]> = indices.chunks(self.batch_size.max(1)).collect();
+        // each group's gradient is the mean over all of its examples, so a
+        // short batch or a short final group isn't over-weighted
+        for group in batches.chunks(self.accumulation_steps.max(1)) {
+            let group_len: usize = group.iter().map(|batch| batch.len()).sum();
+            for batch in group.iter() {
+                let batch: Vec<&(Vec<f64>, Vec<f64>)> = batch.iter().map(|&idx| &data[idx]).collect();
+                let scale = batch.len() as f64 / group_len as f64;
+                total_loss += self.accumulate(&batch, scale) * batch.len() as f64;
+            }
+            update_ratios.extend(self.apply_update(learning_rate));
+        }
+
+        total_loss / data.len() as f64
+    }
+
+    // forward, backward and update on one batch, returns its mean loss
+    fn train_step(&mut self, batch: &[&(Vec<f64>, Vec<f64>)], learning_rate: f64) -> f64 {
+        let loss = self.accumulate(batch, 1.0);
+        self.apply_update(learning_rate);
+        loss
+    }
+
+    // forward and backward on one batch, adding `scale` times the gradient of
+    // its mean loss to the parameters' grads. returns the unscaled mean loss.
+    // the mean is over the batch's actual length, so a short final batch
+    // isn't scaled down relative to full ones
+    fn accumulate(&mut self, batch: &[&(Vec<f64>, Vec<f64>)], scale: f64) -> f64 {
+        let mut batch_loss = Node::constant(0.0);
+        for (inputs, targets) in batch.iter() {
+            let x: Vec<Node> = inputs.iter()
+                .map(|&val| Node::new(val))
+                .collect();
+            let outputs = self.mlp.forward_train(x, &mut self.rng);
+            batch_loss = batch_loss + self.loss.forward(&outputs, targets);
+        }
+        let loss = batch_loss * Node::constant(1.0 / batch.len() as f64);
+
+        loss.set_grad(scale);
+        loss.backward_pass();
+        loss.val()
+    }
+
+    // one optimizer step. returns the per-layer update-to-weight ratios when
+    // track_update_ratios is set
+    fn apply_update(&mut self, learning_rate: f64) -> Option<Vec<f64>> {
+        let before = self.track_update_ratios.then(|| {
+            self.mlp.layer_parameters().iter().map(|params| params_to_vec(params)).collect::<Vec<_>>()
+        });
+        self.mlp.update_params(learning_rate);
+        self.mlp.zero_grad();
+
+        before.map(|before| {
+            self.mlp.layer_parameters()
+                .iter()
+                .zip(before.iter())
+                .map(|(params, before)| update_ratio(before, &params_to_vec(params)))
+                .collect()
+        })
+    }
+
+    // learning rate range test: takes `steps` mini-batch SGD steps with the lr
+    // growing exponentially from min_lr to max_lr and records (lr, batch loss)
+    // at each. batches of batch_size cycle through a shuffled copy of data.
+    // pick an lr a bit below where the loss falls fastest. the model's
+    // weights are restored afterwards, and the trainer's rng is left
+    // untouched so a following fit shuffles as if this never ran
+    pub fn lr_finder(&mut self, data: &[(Vec<f64>, Vec<f64>)], min_lr: f64, max_lr: f64, steps: usize) -> Vec<(f64, f64)> {
+        assert!(min_lr > 0.0 && max_lr > min_lr, "lr_finder needs 0 < min_lr < max_lr");
+        assert!(!data.is_empty(), "lr_finder needs at least one example");
+        let params = self.mlp.parameters();
+        let saved = params_to_vec(&params);
+
+        let mut indices: Vec<usize> = (0..data.len()).collect();
+        indices.shuffle(&mut self.rng.clone());
+        let batches: Vec<Vec<&(Vec<f64>, Vec<f64>)>> = indices.chunks(self.batch_size.max(1))
+            .map(|batch| batch.iter().map(|&idx| &data[idx]).collect())
+            .collect();
+
+        let mut history = Vec::new();
+        for step in 0..steps {
+            let t = if steps > 1 { step as f64 / (steps - 1) as f64 } else { 0.0 };
+            let lr = min_lr * (max_lr / min_lr).powf(t);
+            history.push((lr, self.train_step(&batches[step % batches.len()], lr)));
+        }
+
+        params_from_vec(&params, &saved);
+        self.mlp.zero_grad();
+        history
+    }
+
+    fn should_log(&self, epoch: usize) -> bool {
+        self.verbose && epoch.is_multiple_of(self.log_every.max(1))
+    }
+
+    // average loss (the trainer's own) and accuracy of its model over `data`
+    pub fn evaluate(&self, data: &[(Vec<f64>, Vec<f64>)]) -> (f64, f64) {
+        evaluate_with_loss(&self.mlp, data, self.threshold, self.loss.as_ref())
+    }
+}
+
+// average mean squared error and accuracy over `data`, the numbers a
+// default Trainer reports. outputs above `threshold` count as class 1
+pub fn evaluate(mlp: &MLP, data: &[(Vec<f64>, Vec<f64>)], threshold: f64) -> (f64, f64) {
+    evaluate_with_loss(mlp, data, threshold, &MseLoss)
+}
+
+// like evaluate with any loss, computed from predict's outputs so no graph
+// is built for the model
+pub fn evaluate_with_loss(mlp: &MLP, data: &[(Vec<f64>, Vec<f64>)], threshold: f64, loss: &dyn Loss) -> (f64, f64) {
+    let mut total_loss = 0.0;
+    let mut correct = 0;
+
+    for (inputs, targets) in data.iter() {
+        let outputs = mlp.predict(inputs);
+        total_loss += loss.eval(&outputs, targets);
+
+        let predicted_right = binary_predict(&outputs, threshold)
+            .iter()
+            .zip(targets.iter())
+            .all(|(p, t)| (p - t).abs() < 1e-5);
+        if predicted_right {
+            correct += 1;
+        }
+    }
+
+    let n = data.len() as f64;
+    (total_loss / n, correct as f64 / n)
+}
+
+// ||after - before|| / ||before||; a layer whose parameters are all zero
+// reports 0.0 if nothing moved and infinity otherwise
+fn update_ratio(before: &[f64], after: &[f64]) -> f64 {
+    let update = before.iter().zip(after.iter()).map(|(b, a)| (a - b) * (a - b)).sum::<f64>().sqrt();
+    let weight = before.iter().map(|b| b * b).sum::<f64>().sqrt();
+    if weight > 0.0 {
+        update / weight
+    } else if update > 0.0 {
+        f64::INFINITY
+    } else {
+        0.0
+    }
+}
+
+// maps raw outputs to class labels: 1.0 above the threshold, 0.0 otherwise.
+// for tanh outputs in [-1, 1] a threshold of 0.0 is the natural midpoint
+pub fn binary_predict(outputs: &[f64], threshold: f64) -> Vec<f64> {
+    outputs.iter().map(|&o| if o > threshold { 1.0 } else { 0.0 }).collect()
+}
+
+// gradient-free training with a (1 + lambda) evolution strategy: each
+// generation perturbs the current best parameters `population` times with
+// gaussian noise of std `sigma`, scores every candidate with evaluate's loss,
+// and keeps the best one if it beats the parent. the model ends up holding the
+// best parameters found; returns the best loss after each generation
+pub fn evolve(
+    mlp: &mut MLP,
+    data: &[(Vec<f64>, Vec<f64>)],
+    population: usize,
+    generations: usize,
+    sigma: f64,
+    rng: &mut impl Rng,
+) -> Vec<f64> {
+    assert!(population > 0, "evolve needs a population of at least 1");
+    let params = mlp.parameters();
+    let loss = |mlp: &MLP| evaluate(mlp, data, 0.5).0;
+
+    let mut best = params_to_vec(&params);
+    let mut best_loss = loss(mlp);
+    let mut history = Vec::with_capacity(generations);
+    for _ in 0..generations {
+        // every child is a mutation of this generation's parent
+        let children: Vec<(f64, Vec<f64>)> = (0..population)
+            .map(|_| {
+                let child: Vec<f64> = best.iter()
+                    .map(|v| v + sigma * standard_normal(rng))
+                    .collect();
+                params_from_vec(&params, &child);
+                (loss(mlp), child)
+            })
+            .collect();
+
+        let (child_loss, child) = children.into_iter()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .expect("population is at least 1");
+        if child_loss < best_loss {
+            best = child;
+            best_loss = child_loss;
+        }
+        history.push(best_loss);
+    }
+
+    params_from_vec(&params, &best);
+    history
+}
+
+// k-fold cross-validation: for each fold, trains a fresh trainer from
+// build_fn on the other k-1 folds and returns the accuracy on the held-out one.
+// folds are contiguous, so shuffle `data` first if it is ordered
+pub fn cross_validate(
+    data: &[(Vec<f64>, Vec<f64>)],
+    k: usize,
+    mut build_fn: impl FnMut() -> Trainer,
+    epochs: usize,
+) -> Vec<f64> {
+    assert!(k >= 2 && k <= data.len(), "cross_validate needs 2 <= k <= data.len()");
+    let fold_bounds = |i: usize| (i * data.len() / k, (i + 1) * data.len() / k);
+
+    (0..k)
+        .map(|i| {
+            let (start, end) = fold_bounds(i);
+            let held_out = &data[start..end];
+            let train: Vec<(Vec<f64>, Vec<f64>)> = data[..start].iter()
+                .chain(data[end..].iter())
+                .cloned()
+                .collect();
+
+            let trainer = build_fn();
+            let threshold = trainer.threshold;
+            let (mlp, _) = trainer.fit(&train, epochs);
+            evaluate(&mlp, held_out, threshold).1
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loss::CrossEntropyLoss;
+    use crate::testing::assert_close;
+    use rand::Rng;
+
+    #[test]
+    fn evaluate_gives_full_accuracy_to_a_correct_model() {
+        // weights and bias start in [-0.1, 0.1], so the output stays well
+        // below the 0.5 threshold for these inputs and every example is class 0
+        let trainer = Trainer::new(MLP::new(1, vec![1]), 0.1);
+        let (loss, accuracy) = trainer.evaluate(&[(vec![1.0], vec![0.0]), (vec![-1.0], vec![0.0])]);
+        assert_eq!(accuracy, 1.0);
+        assert!(loss < 0.05, "{}", loss);
+        assert_eq!(trainer.evaluate(&[(vec![1.0], vec![1.0])]).1, 0.0);
+    }
+
+    #[test]
+    fn log_every_controls_which_epochs_are_logged() {
+        let lines = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        let mut trainer = Trainer::new(one_neuron(), 0.1);
+        trainer.verbose = true;
+        trainer.log_every = 5;
+        trainer.logger = Logger::new(move |line| sink.lock().unwrap().push(line.to_string()));
+        trainer.fit(&[(vec![0.5, -0.25], vec![1.0])], 12);
+
+        let epochs: Vec<String> = lines.lock().unwrap().iter()
+            .map(|line| line.split(':').next().unwrap().to_string())
+            .collect();
+        assert_eq!(epochs, vec!["Epoch 0", "Epoch 5", "Epoch 10"]);
+    }
+
+    #[test]
+    fn fit_with_validation_records_one_loss_per_epoch() {
+        let data = vec![(vec![0.5, -0.25], vec![1.0]), (vec![-1.0, 0.75], vec![0.0])];
+        let (_, history) = seeded(one_neuron(), 0.1).fit_with_validation(&data, &data, 7);
+        assert_eq!(history.train_losses.len(), 7);
+        assert_eq!(history.val_losses.len(), 7);
+
+        // stopping early cuts both series at the same epoch
+        let mut trainer = seeded(one_neuron(), 0.1);
+        trainer.target_loss = f64::INFINITY;
+        let (_, history) = trainer.fit_with_validation(&data, &data, 7);
+        assert_eq!((history.train_losses.len(), history.val_losses.len()), (1, 1));
+    }
+
+    #[test]
+    fn same_seed_runs_are_identical() {
+        let run = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let data = crate::data::spiral(20, 0.1, &mut rng);
+            let mut trainer = Trainer::new(MLP::with_rng(2, vec![8, 1], &mut rng), 0.05);
+            trainer.rng = rng;
+            trainer.fit(&data, 5).1.train_losses
+        };
+        assert_eq!(run(11), run(11));
+        assert_ne!(run(11), run(12));
+    }
+
+    #[test]
+    fn lr_finder_spans_the_range_and_restores_weights() {
+        let data = vec![(vec![0.5, -0.25], vec![1.0]), (vec![-1.0, 0.75], vec![0.0])];
+        let mut trainer = Trainer::new(one_neuron(), 0.1);
+        trainer.rng = StdRng::seed_from_u64(3);
+        let before: Vec<f64> = trainer.mlp.parameters().iter().map(|p| p.val()).collect();
+
+        let history = trainer.lr_finder(&data, 1e-4, 1.0, 9);
+        assert_eq!(history.len(), 9);
+        assert!((history[0].0 - 1e-4).abs() < 1e-15);
+        assert!((history[8].0 - 1.0).abs() < 1e-12);
+        assert!(history.windows(2).all(|pair| pair[1].0 > pair[0].0));
+
+        let after: Vec<f64> = trainer.mlp.parameters().iter().map(|p| p.val()).collect();
+        assert_eq!(after, before);
+        assert!(trainer.mlp.parameters().iter().all(|p| p.grad() == 0.0));
+        // the shuffle used a copy of the rng
+        assert_eq!(trainer.rng.gen::<u64>(), StdRng::seed_from_u64(3).gen::<u64>());
+    }
+
+    #[test]
+    #[should_panic(expected = "lr_finder needs at least one example")]
+    fn lr_finder_rejects_empty_data() {
+        Trainer::new(one_neuron(), 0.1).lr_finder(&[], 1e-4, 1.0, 3);
+    }
+
+    #[test]
+    fn cross_validate_with_two_folds() {
+        let data: Vec<(Vec<f64>, Vec<f64>)> = [1.0, -1.0, 2.0, -2.0]
+            .iter()
+            .map(|&x| (vec![x], vec![if x > 0.0 { 1.0 } else { 0.0 }]))
+            .collect();
+        let mut built = 0;
+        let accuracies = cross_validate(&data, 2, || {
+            built += 1;
+            // already correct, and a zero lr keeps it that way
+            seeded(MLP::from_weights(vec![(vec![vec![10.0]], vec![0.0])]), 0.0)
+        }, 3);
+        assert_eq!(built, 2);
+        assert_eq!(accuracies, vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn binary_predict_uses_the_threshold() {
+        assert_eq!(binary_predict(&[0.6], 0.7), vec![0.0]);
+        assert_eq!(binary_predict(&[0.6], 0.5), vec![1.0]);
+        assert_eq!(binary_predict(&[0.8, -0.2], 0.0), vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn epoch_loss_is_the_mean_over_examples_with_a_short_batch() {
+        // 5 examples in batches of 2; a zero lr keeps the model fixed
+        let data: Vec<(Vec<f64>, Vec<f64>)> = (0..5)
+            .map(|i| (vec![i as f64 * 0.3 - 0.6, 0.2], vec![if i % 2 == 0 { 1.0 } else { -1.0 }]))
+            .collect();
+        let mut trainer = seeded(one_neuron(), 0.0);
+        trainer.batch_size = 2;
+        let expected = evaluate(&trainer.mlp, &data, 0.5).0;
+        let (_, history) = trainer.fit(&data, 1);
+        assert_close(history.train_losses[0], expected, 1e-12);
+    }
+
+    #[test]
+    fn lr_finder_steps_over_whole_batches() {
+        let data = vec![(vec![0.5, -0.25], vec![1.0]), (vec![-1.0, 0.75], vec![0.0])];
+        let mut trainer = seeded(one_neuron(), 0.1);
+        trainer.batch_size = 2;
+        let expected = trainer.evaluate(&data).0;
+        // the first step sees the untouched model on the one batch holding all of data
+        let history = trainer.lr_finder(&data, 1e-4, 1.0, 3);
+        assert_close(history[0].1, expected, 1e-12);
+    }
+
+    #[test]
+    fn fit_returns_the_trained_model() {
+        let data = vec![(vec![1.0, 0.5], vec![1.0]), (vec![-1.0, -0.5], vec![0.0])];
+        let (before, _) = evaluate(&one_neuron(), &data, 0.5);
+        let (model, history) = seeded(one_neuron(), 0.2).fit(&data, 200);
+
+        let (loss, accuracy) = evaluate(&model, &data, 0.5);
+        assert_eq!(accuracy, 1.0);
+        assert!(loss < before, "{} vs {}", loss, before);
+        assert!(loss <= history.train_losses[0]);
+    }
+
+    #[test]
+    fn restore_best_keeps_the_lowest_validation_epoch() {
+        // training pushes the output towards 1, the validation target is 0.3,
+        // so the validation loss falls and then rises again
+        let data = vec![(vec![1.0], vec![1.0])];
+        let val = vec![(vec![1.0], vec![0.3])];
+        let run = |restore_best: bool| {
+            let mut trainer = seeded(MLP::from_weights(vec![(vec![vec![0.0]], vec![0.0])]), 0.1);
+            trainer.restore_best = restore_best;
+            trainer.fit_with_validation(&data, &val, 10)
+        };
+
+        let (last, history) = run(false);
+        let best = history.val_losses.iter().cloned().fold(f64::INFINITY, f64::min);
+        let best_epoch = history.val_losses.iter().position(|&l| l == best).unwrap();
+        assert!(0 < best_epoch && best_epoch < 9, "{:?}", history.val_losses);
+        assert_eq!(evaluate(&last, &val, 0.5).0, history.val_losses[9]);
+
+        let (restored, restored_history) = run(true);
+        assert_eq!(restored_history, history);
+        assert_close(evaluate(&restored, &val, 0.5).0, best, 1e-12);
+    }
+
+    #[test]
+    fn history_to_csv_writes_one_row_per_epoch() {
+        let path = std::env::temp_dir().join(format!("ember-history-{}.csv", std::process::id()));
+        let mut history = History { train_losses: vec![0.5, 0.25], ..History::default() };
+        history.to_csv(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "epoch,train_loss\n0,0.5\n1,0.25\n");
+
+        history.val_losses = vec![0.75, 0.125];
+        history.to_csv(&path).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(csv.lines().collect::<Vec<&str>>(), vec!["epoch,train_loss,val_loss", "0,0.5,0.75", "1,0.25,0.125"]);
+    }
+
+    // parameters after one epoch with the given batching
+    fn params_after_epoch(data: &[(Vec<f64>, Vec<f64>)], batch_size: usize, accumulation_steps: usize) -> Vec<f64> {
+        let mut trainer = seeded(one_neuron(), 0.5);
+        trainer.batch_size = batch_size;
+        trainer.accumulation_steps = accumulation_steps;
+        let (mlp, _) = trainer.fit(data, 1);
+        mlp.parameters().iter().map(|p| p.val()).collect()
+    }
+
+    #[test]
+    fn accumulating_identical_examples_steps_like_one_example() {
+        let example = (vec![0.5, -0.25], vec![1.0]);
+        let data = vec![example.clone(); 4];
+
+        let mut single = seeded(one_neuron(), 0.5);
+        single.train_step(&[&example], 0.5);
+        let expected: Vec<f64> = single.mlp.parameters().iter().map(|p| p.val()).collect();
+
+        for got in [params_after_epoch(&data, 1, 4), params_after_epoch(&data, 4, 1)] {
+            for (g, e) in got.iter().zip(expected.iter()) {
+                assert_close(*g, *e, 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn uneven_batches_in_a_group_match_one_big_batch() {
+        // batch_size 2 and accumulation 2 over 3 examples is one group of a
+        // 2-batch and a 1-batch, which must step like a single 3-batch
+        let data = vec![
+            (vec![0.5, -0.25], vec![1.0]),
+            (vec![-1.0, 0.75], vec![0.0]),
+            (vec![0.2, 0.9], vec![-1.0]),
+        ];
+        let accumulated = params_after_epoch(&data, 2, 2);
+        let full = params_after_epoch(&data, 3, 1);
+        for (a, f) in accumulated.iter().zip(full.iter()) {
+            assert_close(*a, *f, 1e-12);
+        }
+    }
+
+    #[test]
+    fn evolve_reaches_a_low_loss_on_a_trivial_dataset() {
+        let data = vec![(vec![1.0, 0.5], vec![0.8]), (vec![-1.0, -0.5], vec![-0.8])];
+        let mut mlp = one_neuron();
+        let start = evaluate(&mlp, &data, 0.5).0;
+        let history = evolve(&mut mlp, &data, 10, 50, 0.2, &mut StdRng::seed_from_u64(0));
+
+        assert_eq!(history.len(), 50);
+        assert!(history.windows(2).all(|w| w[1] <= w[0]));
+        let end = evaluate(&mlp, &data, 0.5).0;
+        assert_eq!(end, history[49]);
+        assert!(end < 1e-3 && end < start, "{} -> {}", start, end);
+    }
+
+    // d (sum of outputs) / d outputs is 1 everywhere, unlike squared error
+    #[derive(Debug)]
+    struct SumLoss;
+    impl Loss for SumLoss {
+        fn forward(&self, outputs: &[Node], _targets: &[f64]) -> Node {
+            outputs.iter().sum()
+        }
+    }
+
+    #[test]
+    fn swapping_the_loss_changes_the_gradients() {
+        let example = (vec![0.5, -0.25], vec![1.0]);
+        let grads_with = |loss: Arc<dyn Loss>| {
+            let mut trainer = seeded(one_neuron(), 0.1);
+            trainer.loss = loss;
+            trainer.accumulate(&[&example], 1.0);
+            trainer.mlp.parameters().iter().map(|p| p.grad()).collect::<Vec<f64>>()
+        };
+
+        let mse = grads_with(Arc::new(MseLoss));
+        let sum = grads_with(Arc::new(SumLoss));
+        assert_ne!(mse, sum);
+        // d tanh(z) / d b for the single neuron
+        let z: f64 = 0.3 * 0.5 + -0.2 * -0.25 + 0.1;
+        assert_close(sum[2], 1.0 - z.tanh().powi(2), 1e-12);
+    }
+
+    #[test]
+    fn validation_uses_the_configured_loss() {
+        let mlp = MLP::from_weights(vec![(vec![vec![0.5], vec![-0.5]], vec![0.0, 0.0])]);
+        let data = vec![(vec![1.0], vec![1.0, 0.0]), (vec![-1.0], vec![0.0, 1.0])];
+        let mut trainer = seeded(mlp, 0.0);
+        trainer.loss = Arc::new(CrossEntropyLoss);
+        let (mlp, history) = trainer.fit_with_validation(&data, &data, 1);
+
+        let expected = evaluate_with_loss(&mlp, &data, 0.5, &CrossEntropyLoss).0;
+        assert_close(history.val_losses[0], expected, 1e-12);
+        // with a zero lr the train loss is the same loss on the same data
+        assert_close(history.train_losses[0], expected, 1e-12);
+        assert!((expected - evaluate(&mlp, &data, 0.5).0).abs() > 1e-3);
+    }
+
+    #[test]
+    fn update_ratio_matches_a_hand_computed_step() {
+        let mut trainer = seeded(one_neuron(), 0.1);
+        trainer.track_update_ratios = true;
+        let (_, history) = trainer.fit(&[(vec![0.5, -0.25], vec![1.0])], 1);
+
+        // d loss / d z for (tanh(z) - 1)^2, then each grad clipped to [-1, 1]
+        let z: f64 = 0.3 * 0.5 + -0.2 * -0.25 + 0.1;
+        let g = 2.0 * (z.tanh() - 1.0) * (1.0 - z.tanh().powi(2));
+        let grads = [0.5 * g, -0.25 * g, g].map(|g: f64| g.clamp(-1.0, 1.0));
+        let update = 0.1 * grads.iter().map(|g| g * g).sum::<f64>().sqrt();
+        let weight = (0.3f64.powi(2) + 0.2f64.powi(2) + 0.1f64.powi(2)).sqrt();
+
+        assert_eq!(history.update_ratios.len(), 1);
+        assert_eq!(history.update_ratios[0].len(), 1);
+        assert_close(history.update_ratios[0][0], update / weight, 1e-12);
+        assert_eq!(update_ratio(&[0.0], &[0.0]), 0.0);
+        assert_eq!(update_ratio(&[0.0], &[1.0]), f64::INFINITY);
+    }
+
+    fn seeded(mlp: MLP, learning_rate: f64) -> Trainer {
+        let mut trainer = Trainer::new(mlp, learning_rate);
+        trainer.rng = StdRng::seed_from_u64(0);
+        trainer
+    }
+
+    fn one_neuron() -> MLP {
+        MLP::from_weights(vec![(vec![vec![0.3, -0.2]], vec![0.1])])
+    }
+}
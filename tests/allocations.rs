@@ -0,0 +1,102 @@
+// allocation counts for the paths that exist to save allocations. the
+// counter is per thread so tests running in parallel don't see each
+// other's allocations
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use ember::arena::Graph;
+use ember::grad::{Layer, Neuron, Node};
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// (result, number of allocations made while computing it)
+fn counting<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(|n| n.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|n| n.get()) - before)
+}
+
+const CHAIN: usize = 1000;
+
+#[test]
+fn arena_chain_allocates_less_with_identical_gradients() {
+    // y <- tanh(y * w + b), CHAIN times
+    let ((graph, x, w, b, out), arena_allocs) = counting(|| {
+        let mut g = Graph::with_capacity(3 + 3 * CHAIN);
+        let (x, w, b) = (g.value(0.5), g.value(0.9), g.value(0.1));
+        let mut y = x;
+        for _ in 0..CHAIN {
+            let yw = g.mul(y, w);
+            let z = g.add(yw, b);
+            y = g.tanh(z);
+        }
+        (g, x, w, b, y)
+    });
+
+    let ((nx, nw, nb, nout), node_allocs) = counting(|| {
+        let (x, w, b) = (Node::new(0.5), Node::new(0.9), Node::new(0.1));
+        let mut y = x.clone();
+        for _ in 0..CHAIN {
+            y = (y * w.clone() + b.clone()).tanh();
+        }
+        (x, w, b, y)
+    });
+
+    assert!(
+        arena_allocs < node_allocs,
+        "arena made {} allocations, Node made {}", arena_allocs, node_allocs
+    );
+
+    let mut graph = graph;
+    graph.set_grad(out, 1.0);
+    graph.backward(out);
+    nout.set_grad(1.0);
+    nout.backward_pass();
+
+    assert_eq!(graph.val(out), nout.val());
+    assert_eq!(graph.grad(x), nx.grad());
+    assert_eq!(graph.grad(w), nw.grad());
+    assert_eq!(graph.grad(b), nb.grad());
+}
+
+#[test]
+fn layer_forward_shares_the_input_slice() {
+    let w: Vec<Vec<f64>> = (0..32).map(|i| (0..16).map(|j| ((i * 16 + j) as f64 * 0.01).sin()).collect()).collect();
+    let b: Vec<f64> = (0..32).map(|i| i as f64 * 0.01).collect();
+    let layer = Layer::from_weights(w.clone(), b.clone());
+    let neurons: Vec<Neuron> = w.into_iter().zip(b).map(|(w, b)| Neuron::from_weights(w, b)).collect();
+    let x: Vec<Node> = (0..16).map(|i| Node::new(i as f64 * 0.1 - 0.8)).collect();
+
+    let (shared, shared_allocs) = counting(|| layer.forward(x.clone()));
+    // what forward used to do: a copy of the input vector for every neuron
+    let (copied, copied_allocs) = counting(|| {
+        let x = x.clone();
+        neurons.iter().map(|neuron| neuron.forward(&x.to_vec())).collect::<Vec<Node>>()
+    });
+
+    let shared: Vec<f64> = shared.iter().map(|y| y.val()).collect();
+    let copied: Vec<f64> = copied.iter().map(|y| y.val()).collect();
+    assert_eq!(shared, copied);
+    assert!(
+        shared_allocs < copied_allocs,
+        "shared input made {} allocations, per-neuron copies made {}", shared_allocs, copied_allocs
+    );
+}